@@ -0,0 +1,121 @@
+#![feature(test)]
+
+extern crate test;
+
+use acl::{
+    gen_h, gen_h_table, BatchableSignature, PrecomputedVerifyingKey, Signature, SigningKey,
+    UserParameters, VerifyingKey,
+};
+use curve25519_dalek::ristretto::RistrettoPoint;
+use curve25519_dalek::scalar::Scalar;
+use rand_core::OsRng;
+use test::{black_box, Bencher};
+
+fn issue(signing_key: &SigningKey) -> (RistrettoPoint, Signature) {
+    let commitment = RistrettoPoint::random(&mut OsRng);
+    let user_params = UserParameters {
+        key: VerifyingKey::from(signing_key),
+    };
+
+    let (ss, prepare_message) = signing_key.prepare(&commitment).unwrap();
+    let (us, challenge) = user_params
+        .compute_challenge(&mut OsRng, &commitment, &[0u8; 64], &prepare_message)
+        .unwrap();
+    let presignature = signing_key.compute_presignature(ss, &challenge).unwrap();
+    let credential = user_params.compute_signature(&us, &presignature).unwrap();
+
+    (credential.blinded_commitment, credential.signature)
+}
+
+#[bench]
+fn mul_h_variable_base(b: &mut Bencher) {
+    let s = Scalar::random(&mut OsRng);
+    b.iter(|| black_box(gen_h() * s));
+}
+
+#[bench]
+fn mul_h_table(b: &mut Bencher) {
+    let s = Scalar::random(&mut OsRng);
+    b.iter(|| black_box(gen_h_table() * &s));
+}
+
+#[bench]
+fn prepare(b: &mut Bencher) {
+    let signing_key = SigningKey::from_bytes(&[7u8; 32]);
+    let commitment = RistrettoPoint::random(&mut OsRng);
+    b.iter(|| signing_key.prepare(&commitment).unwrap());
+}
+
+#[bench]
+fn verify_prehashed(b: &mut Bencher) {
+    let signing_key = SigningKey::from_bytes(&[7u8; 32]);
+    let key = VerifyingKey::from(&signing_key);
+    let (commitment, signature) = issue(&signing_key);
+    b.iter(|| key.verify_prehashed(&[0u8; 64], &commitment, &signature).unwrap());
+}
+
+#[bench]
+fn verify_prehashed_precomputed(b: &mut Bencher) {
+    let signing_key = SigningKey::from_bytes(&[7u8; 32]);
+    let key = PrecomputedVerifyingKey::from(&VerifyingKey::from(&signing_key));
+    let (commitment, signature) = issue(&signing_key);
+    b.iter(|| key.verify_prehashed(&[0u8; 64], &commitment, &signature).unwrap());
+}
+
+#[bench]
+fn verify_prehashed_loop_64(b: &mut Bencher) {
+    let signing_key = SigningKey::from_bytes(&[7u8; 32]);
+    let key = PrecomputedVerifyingKey::from(&VerifyingKey::from(&signing_key));
+    let issued: Vec<(RistrettoPoint, Signature)> = (0..64).map(|_| issue(&signing_key)).collect();
+    b.iter(|| {
+        for (commitment, signature) in issued.iter() {
+            key.verify_prehashed(&[0u8; 64], commitment, signature).unwrap();
+        }
+    });
+}
+
+fn batchable_64(key: &PrecomputedVerifyingKey, signing_key: &SigningKey) -> Vec<(RistrettoPoint, BatchableSignature)> {
+    (0..64)
+        .map(|_| {
+            let (commitment, signature) = issue(signing_key);
+            (commitment, key.batchable(&[0u8; 64], &commitment, &signature).unwrap())
+        })
+        .collect()
+}
+
+#[bench]
+fn verify_batch_64(b: &mut Bencher) {
+    let signing_key = SigningKey::from_bytes(&[7u8; 32]);
+    let key = PrecomputedVerifyingKey::from(&VerifyingKey::from(&signing_key));
+    let issued: Vec<(RistrettoPoint, Signature)> = (0..64).map(|_| issue(&signing_key)).collect();
+    let batch: Vec<(&[u8], &RistrettoPoint, &Signature)> = issued
+        .iter()
+        .map(|(commitment, signature)| (&[0u8; 64][..], commitment, signature))
+        .collect();
+    b.iter(|| key.verify_batch(&batch).unwrap());
+}
+
+#[bench]
+fn verify_batchable_64(b: &mut Bencher) {
+    let signing_key = SigningKey::from_bytes(&[7u8; 32]);
+    let key = PrecomputedVerifyingKey::from(&VerifyingKey::from(&signing_key));
+    let issued = batchable_64(&key, &signing_key);
+    let batch: Vec<(&[u8], &RistrettoPoint, &BatchableSignature)> = issued
+        .iter()
+        .map(|(commitment, signature)| (&[0u8; 64][..], commitment, signature))
+        .collect();
+    b.iter(|| key.verify_batchable(&batch).unwrap());
+}
+
+#[bench]
+fn verify_batchable_64_one_invalid(b: &mut Bencher) {
+    let signing_key = SigningKey::from_bytes(&[7u8; 32]);
+    let key = PrecomputedVerifyingKey::from(&VerifyingKey::from(&signing_key));
+    let mut issued = batchable_64(&key, &signing_key);
+    issued[17].1.signature.rho += Scalar::ONE;
+    let batch: Vec<(&[u8], &RistrettoPoint, &BatchableSignature)> = issued
+        .iter()
+        .map(|(commitment, signature)| (&[0u8; 64][..], commitment, signature))
+        .collect();
+    b.iter(|| key.verify_batchable(&batch).unwrap_err());
+}
@@ -2,32 +2,60 @@
 
 #[macro_use] extern crate rocket;
 
-use serde::{Serialize,Deserialize};
+use acl::endpoints::{self, IssuerConfig};
+use acl::web::{CredentialConfig, CredentialSchema, Presented};
+use acl::{MemorySpentTokenStore, PresentationPolicy, Schema, SigningKey, VerifyingKey};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use curve25519_dalek::scalar::Scalar;
+use rocket::http::Status;
+use rocket::Request;
+use serde::Serialize;
 use rocket_contrib::json::Json;
-use chrono::{Duration,Utc,NaiveTime};
-use jsonwebtoken::{decode,encode,get_current_timestamp,Algorithm,DecodingKey,EncodingKey,Header,Validation};
+use std::sync::Arc;
 
-#[derive(Serialize, Deserialize)]
-struct Credentials {
-    username: String,
-    password: String,
+struct Account {
+    username: &'static str,
+    password: &'static str,
+    sports: bool,
+    tech: bool,
 }
 
-#[derive(Debug,Serialize,Deserialize)]
-struct Claims {
-    exp: u64,
-    id: u64,
+const ACCOUNTS: &[Account] = &[
+    Account { username: "alice", password: "correct horse", sports: true, tech: false },
+    Account { username: "bob", password: "battery staple", sports: false, tech: true },
+];
+
+const SUBSCRIBER: u8 = 2;
+
+struct Subscriber;
+
+// subscribers show their type and subscriptions to read the news, and keep
+// their user id hidden
+impl CredentialSchema for Subscriber {
+    const ATTRIBUTES: &'static [&'static str] = &["user id", "type", "sports", "tech"];
+
+    fn policy(_: &Request) -> PresentationPolicy {
+        PresentationPolicy {
+            revealed: vec![1, 2, 3],
+            ..Default::default()
+        }
+    }
 }
 
-const key: &[u8] = b"99023a4b-186c-41b8-9817-0a0d232f2402";
+// authenticate checks HTTP basic credentials and grants the account's
+// subscriptions; the user id (index 0) is the user's own and stays hidden
+fn authenticate(request: &Request) -> Option<Vec<(usize, Scalar)>> {
+    let encoded = request.headers().get_one("Authorization")?.strip_prefix("Basic ")?;
+    let decoded = String::from_utf8(STANDARD.decode(encoded).ok()?).ok()?;
+    let (username, password) = decoded.split_once(':')?;
 
-#[post("/login", format = "application/json", data = "<credentials>")]
-fn login(credentials: Json<Credentials>) -> String {
-    let claims = Claims {
-        id: 0x1234,
-        exp: (Utc::now().with_time(NaiveTime::MIN).unwrap() + Duration::days(1)).timestamp() as u64,
-    };
-    encode(&Header::default(), &claims, &EncodingKey::from_secret(key)).expect("asdf")
+    let account = ACCOUNTS.iter().find(|account| account.username == username && account.password == password)?;
+    Some(vec![
+        (1, Scalar::from(SUBSCRIBER)),
+        (2, Scalar::from(account.sports as u8)),
+        (3, Scalar::from(account.tech as u8)),
+    ])
 }
 
 #[derive(Serialize)]
@@ -45,8 +73,19 @@ struct Article {
 }
 
 #[get("/news")]
-fn news() -> Json<Vec<Article>> {
-    Json(Vec::from([Article{headline: "Lorem ipsum".to_string(), content: "bla bla bla bla bla".to_string(), section: Section::General}]))
+fn news(subscriber: Presented<Subscriber>) -> Result<Json<Vec<Article>>, Status> {
+    if subscriber.attribute("type") != Some(&Scalar::from(SUBSCRIBER)) {
+        return Err(Status::Forbidden);
+    }
+
+    let mut articles = vec![Article{headline: "Lorem ipsum".to_string(), content: "bla bla bla bla bla".to_string(), section: Section::General}];
+    if subscriber.attribute("sports") == Some(&Scalar::ONE) {
+        articles.push(Article{headline: "Dolor sit amet".to_string(), content: "bla bla bla".to_string(), section: Section::Sports});
+    }
+    if subscriber.attribute("tech") == Some(&Scalar::ONE) {
+        articles.push(Article{headline: "Consectetur adipiscing".to_string(), content: "bla bla bla".to_string(), section: Section::Tech});
+    }
+    Ok(Json(articles))
 }
 
 #[get("/")]
@@ -55,5 +94,18 @@ fn index() -> &'static str {
 }
 
 fn main() {
-    rocket::ignite().mount("/", routes![index, news, login]).launch();
+    let signing_key = SigningKey::from_bytes(&[7u8; 32]);
+    let credentials = CredentialConfig::<Subscriber>::new(
+        &[VerifyingKey::from(&signing_key)],
+        Arc::new(MemorySpentTokenStore::new()),
+        b"news.example",
+    );
+    let issuer = IssuerConfig::new(signing_key, Schema::new(Subscriber::ATTRIBUTES), authenticate);
+
+    rocket::ignite()
+        .manage(issuer)
+        .manage(credentials)
+        .mount("/", routes![index, news])
+        .mount("/", endpoints::routes())
+        .launch();
 }
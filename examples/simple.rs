@@ -1,14 +1,10 @@
 use acl::{
-    SigningKey, UserParameters, VerifyingKey,SECRET_KEY_LENGTH, gen_h,
+    IssuanceRequest, Opening, Schema, SigningKey, UserParameters, VerifyingKey, SECRET_KEY_LENGTH,
 };
-use curve25519_dalek::ristretto::RistrettoPoint;
 use curve25519_dalek::scalar::Scalar;
-use group::GroupEncoding;
 
 use rand_core::OsRng;
 
-use sha2::Sha512;
-
 #[derive(Copy, Clone, Debug)]
 #[repr(u8)]
 enum UserType {
@@ -49,9 +45,6 @@ fn main() {
 
     let signing_key: SigningKey = SigningKey::from_bytes(&secret_key_bytes);
 
-    let (ss, msg) = signing_key.prepare(&[0u8; 32]).expect("this should fail");
-    let presig = signing_key.compute_presignature(&ss, &[0u8; 32]);
-
     let bob = UserAttributes {
         user_id: 1,
         user_type: UserType::Subscriber,
@@ -66,16 +59,33 @@ fn main() {
         UserAttributeID::Tech.as_bytes(),
     ];
 
-    let generators: Vec<RistrettoPoint> = attribute_ids.into_iter().map(|id| RistrettoPoint::hash_from_bytes::<Sha512>(&id)).collect();
+    let schema = Schema::new(&attribute_ids);
+
+    // bob only knows his own user id; the issuer decides his type and
+    // subscriptions, so those are left as placeholders in his opening
+    let mut opening = Opening::new(
+        &mut OsRng,
+        vec![Scalar::from(bob.user_id), Scalar::ZERO, Scalar::ZERO, Scalar::ZERO],
+    );
 
-    let commitment = gen_h() * Scalar::random(&mut OsRng) + generators[0] * Scalar::from(bob.user_id) + generators[1] * Scalar::from(bob.user_type as u128) + generators[2] * Scalar::from(bob.is_sports_subscriber as u128) + generators[3] * Scalar::from(bob.is_tech_subscriber as u128);
+    let request = IssuanceRequest::with_issuer_attributes(&mut OsRng, &schema, &opening, &[], &[1, 2, 3])
+        .expect("opening matches the schema");
 
-    let commit_bytes = commitment.compress().to_bytes();
+    let issuer_attributes = [
+        (1, Scalar::from(bob.user_type as u128)),
+        (2, Scalar::from(bob.is_sports_subscriber as u128)),
+        (3, Scalar::from(bob.is_tech_subscriber as u128)),
+    ];
 
     let (ss, prepare_message) = signing_key
-        .prepare(&commit_bytes)
+        .prepare_with_attributes(&schema, &request, &issuer_attributes)
         .expect("this should work");
 
+    for (index, value) in issuer_attributes {
+        opening.attributes[index] = value;
+    }
+    let commitment = opening.commit(&schema);
+
     let user_params = UserParameters {
         key: VerifyingKey::from(&signing_key),
     };
@@ -85,13 +95,13 @@ fn main() {
         .expect("this should work");
 
     let presignature = signing_key
-        .compute_presignature(&ss, &challenge)
+        .compute_presignature(ss, &challenge)
         .expect("should work");
 
-    let (signature, blinded_commitment, gamma, rnd) = user_params
+    let credential = user_params
         .compute_signature(&us, &presignature)
         .expect("sig should be fine");
 
-    println!("valid: {:?}", user_params.key.verify_prehashed(&[0u8; 64], &blinded_commitment.to_bytes(), &signature));
-    println!("valid: {:?}", user_params.key.verify_prehashed(&[1u8; 64], &blinded_commitment.to_bytes(), &signature));
+    println!("valid: {:?}", credential.verify(&user_params.key));
+    println!("valid: {:?}", user_params.key.verify_prehashed(&[1u8; 64], &credential.blinded_commitment, &credential.signature));
 }
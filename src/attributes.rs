@@ -0,0 +1,68 @@
+use crate::constants::gen_h;
+
+use curve25519_dalek::ristretto::RistrettoPoint;
+use curve25519_dalek::scalar::Scalar;
+use curve25519_dalek::traits::MultiscalarMul;
+
+use rand_core::{CryptoRng, RngCore};
+
+use serde::{Deserialize, Serialize};
+
+use sha2::Sha512;
+
+// Schema fixes the generators that attribute values are committed under. A
+// commitment to attributes m_1..m_n with blinding r is r*H + sum(m_i * G_i),
+// where G_i is hashed from the i-th attribute id.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Schema {
+    pub(crate) generators: Vec<RistrettoPoint>,
+}
+
+impl Schema {
+    pub fn new<I: AsRef<[u8]>>(attribute_ids: &[I]) -> Schema {
+        Schema {
+            generators: attribute_ids
+                .iter()
+                .map(|id| RistrettoPoint::hash_from_bytes::<Sha512>(id.as_ref()))
+                .collect(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.generators.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.generators.is_empty()
+    }
+
+    pub fn generators(&self) -> &[RistrettoPoint] {
+        &self.generators
+    }
+}
+
+// Opening holds the attribute values and blinding factor of a commitment, in
+// schema order. It is the user's secret and never leaves the client.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Opening {
+    pub attributes: Vec<Scalar>,
+    pub blinding: Scalar,
+}
+
+impl Opening {
+    pub fn new<R: RngCore + CryptoRng>(rng: &mut R, attributes: Vec<Scalar>) -> Opening {
+        Opening {
+            attributes,
+            blinding: Scalar::random(rng),
+        }
+    }
+
+    // commit computes r*H + sum(m_i * G_i); the opening is secret, so this uses
+    // constant-time multiscalar multiplication
+    pub fn commit(&self, schema: &Schema) -> RistrettoPoint {
+        RistrettoPoint::multiscalar_mul(
+            [self.blinding].iter().chain(self.attributes.iter()),
+            [gen_h()].into_iter().chain(schema.generators.iter()),
+        )
+    }
+}
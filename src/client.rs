@@ -0,0 +1,416 @@
+// User side of the issuance endpoints, behind the "http" feature. The client
+// is independent of any HTTP library: it talks through a Transport, which the
+// application implements with whatever it already uses. With the "rocket"
+// feature, rocket::local::Client is a Transport, for testing against an
+// in-process server.
+use crate::attributes::{Opening, Schema};
+use crate::errors::ClientError;
+use crate::issuance::IssuanceRequest;
+use crate::messages::{PrepareResponse, PresignRequest, PresignResponse, PREPARE_PATH, PRESIGN_PATH};
+use crate::token::Token;
+use crate::user::UserParameters;
+use crate::verifying::VerifyingKey;
+
+use rand_core::{CryptoRng, RngCore};
+
+use serde::de::DeserializeOwned;
+
+use std::thread;
+use std::time::Duration;
+
+// MAX_BACKOFF caps the delay between two retries
+pub const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+// Transport posts a JSON body to an issuance endpoint, with the given extra
+// headers, and returns the status code and body of the response. Failing to
+// get any response at all is ClientError::Transport.
+pub trait Transport {
+    fn post(&self, path: &str, headers: &[(&str, &str)], body: Vec<u8>) -> Result<(u16, Vec<u8>), ClientError>;
+}
+
+// IssuanceClient runs both rounds of an issuance against one issuer. Requests
+// that fail transiently (no response, or status 429, 502, 503 or 504, e.g.
+// while the issuer's sessions are all taken) are retried with exponential
+// backoff. If the session expires before presign, the issuance starts over
+// with a fresh challenge.
+pub struct IssuanceClient<T: Transport> {
+    transport: T,
+    params: UserParameters,
+    schema: Schema,
+    authorization: Option<String>,
+    retries: u32,
+    backoff: Duration,
+}
+
+impl<T: Transport> IssuanceClient<T> {
+    pub fn new(transport: T, key: VerifyingKey, schema: Schema) -> IssuanceClient<T> {
+        IssuanceClient {
+            transport,
+            params: UserParameters { key },
+            schema,
+            authorization: None,
+            retries: 3,
+            backoff: Duration::from_millis(100),
+        }
+    }
+
+    // with_authorization sets the Authorization header sent to prepare, for
+    // the issuer's Authenticator
+    pub fn with_authorization(mut self, authorization: &str) -> IssuanceClient<T> {
+        self.authorization = Some(authorization.to_string());
+        self
+    }
+
+    // with_retries sets how often a transient failure is retried, and the
+    // delay before the first retry, which doubles with every further one up
+    // to MAX_BACKOFF
+    pub fn with_retries(mut self, retries: u32, backoff: Duration) -> IssuanceClient<T> {
+        self.retries = retries;
+        self.backoff = backoff;
+        self
+    }
+
+    // issue obtains a credential for the opening, revealing the attributes at
+    // revealed to the issuer and leaving those at issuer_assigned to it (see
+    // IssuanceRequest::with_issuer_attributes). The returned token's opening
+    // holds the issuer's values.
+    pub fn issue<R: RngCore + CryptoRng>(
+        &self,
+        rng: &mut R,
+        mut opening: Opening,
+        revealed: &[usize],
+        issuer_assigned: &[usize],
+        hashed_message: &[u8],
+    ) -> Result<Token, ClientError> {
+        let request =
+            IssuanceRequest::with_issuer_attributes(rng, &self.schema, &opening, revealed, issuer_assigned)?;
+        let request = serde_json::to_vec(&request).expect("issuance requests always serialize");
+
+        let mut restarts = 0;
+        loop {
+            let prepared: PrepareResponse = self.retry(PREPARE_PATH, &request)?;
+            if prepared.message.len() != 128
+                || prepared.issuer_attributes.len() != issuer_assigned.len()
+                || prepared
+                    .issuer_attributes
+                    .iter()
+                    .zip(issuer_assigned)
+                    .any(|((index, _), assigned)| index != assigned)
+            {
+                return Err(ClientError::Format);
+            }
+
+            for (index, value) in prepared.issuer_attributes.iter() {
+                opening.attributes[*index] = *value;
+            }
+            let commitment = opening.commit(&self.schema);
+            let (state, challenge) =
+                self.params
+                    .compute_challenge(rng, &commitment, hashed_message, &prepared.message)?;
+
+            let presign = PresignRequest {
+                session: prepared.session,
+                challenge,
+            };
+            let presigned: PresignResponse =
+                match self.retry(PRESIGN_PATH, &serde_json::to_vec(&presign).expect("messages always serialize")) {
+                    Err(ClientError::Status { code: 404 }) if restarts < self.retries => {
+                        restarts += 1;
+                        continue;
+                    }
+                    presigned => presigned?,
+                };
+            if presigned.presignature.len() != 160 {
+                return Err(ClientError::Format);
+            }
+
+            let credential = self.params.compute_signature(&state, &presigned.presignature)?;
+            return Ok(Token::new(credential, opening));
+        }
+    }
+
+    fn retry<M: DeserializeOwned>(&self, path: &str, body: &[u8]) -> Result<M, ClientError> {
+        let mut headers = vec![("Content-Type", "application/json"), ("Accept", "application/json")];
+        if let Some(authorization) = &self.authorization {
+            headers.push(("Authorization", authorization));
+        }
+
+        let mut attempt = 0;
+        loop {
+            let result = match self.transport.post(path, &headers, body.to_vec()) {
+                Ok((200, response)) => serde_json::from_slice(&response).map_err(|_| ClientError::Format),
+                Ok((code, _)) => Err(ClientError::Status { code }),
+                Err(err) => Err(err),
+            };
+
+            match result {
+                Err(ClientError::Transport) | Err(ClientError::Status { code: 429 | 502 | 503 | 504 })
+                    if attempt < self.retries =>
+                {
+                    thread::sleep(self.delay(attempt));
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    fn delay(&self, attempt: u32) -> Duration {
+        2u32.checked_pow(attempt)
+            .and_then(|factor| self.backoff.checked_mul(factor))
+            .map_or(MAX_BACKOFF, |delay| delay.min(MAX_BACKOFF))
+    }
+}
+
+#[cfg(feature = "rocket")]
+impl Transport for rocket::local::Client {
+    fn post(&self, path: &str, headers: &[(&str, &str)], body: Vec<u8>) -> Result<(u16, Vec<u8>), ClientError> {
+        let mut request = rocket::local::Client::post(self, path.to_string()).body(body);
+        for (name, value) in headers {
+            request = request.header(rocket::http::Header::new(name.to_string(), value.to_string()));
+        }
+
+        let mut response = request.dispatch();
+        Ok((response.status().code, response.body_bytes().unwrap_or_default()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::signing::{SignerState, SigningKey};
+    use curve25519_dalek::scalar::Scalar;
+    use rand_core::OsRng;
+    use std::cell::{Cell, RefCell};
+    use std::collections::HashMap;
+
+    // Issuer answers the issuance endpoints in memory. It answers the next
+    // failures requests with 503, forgets the next expired sessions before
+    // their presign, and truncates prepare messages while malformed is set.
+    struct Issuer {
+        key: SigningKey,
+        sessions: RefCell<HashMap<Vec<u8>, SignerState>>,
+        opened: Cell<u8>,
+        requests: Cell<u32>,
+        failures: Cell<u32>,
+        expired: Cell<u32>,
+        malformed: Cell<bool>,
+    }
+
+    impl Issuer {
+        fn new() -> Issuer {
+            Issuer {
+                key: SigningKey::from_bytes(&[7u8; 32]),
+                sessions: RefCell::new(HashMap::new()),
+                opened: Cell::new(0),
+                requests: Cell::new(0),
+                failures: Cell::new(0),
+                expired: Cell::new(0),
+                malformed: Cell::new(false),
+            }
+        }
+    }
+
+    impl Transport for Issuer {
+        fn post(&self, path: &str, _: &[(&str, &str)], body: Vec<u8>) -> Result<(u16, Vec<u8>), ClientError> {
+            self.requests.set(self.requests.get() + 1);
+            if self.failures.get() > 0 {
+                self.failures.set(self.failures.get() - 1);
+                return Ok((503, Vec::new()));
+            }
+
+            match path {
+                PREPARE_PATH => {
+                    let request: IssuanceRequest = serde_json::from_slice(&body).unwrap();
+                    let issuer_attributes = vec![(2, Scalar::from(2030u16))];
+                    let (state, mut message) = self
+                        .key
+                        .prepare_with_attributes(&schema(), &request, &issuer_attributes)
+                        .unwrap();
+
+                    self.opened.set(self.opened.get() + 1);
+                    let session = vec![self.opened.get(); 32];
+                    if self.expired.get() > 0 {
+                        self.expired.set(self.expired.get() - 1);
+                    } else {
+                        self.sessions.borrow_mut().insert(session.clone(), state);
+                    }
+                    if self.malformed.get() {
+                        message.truncate(64);
+                    }
+
+                    let response = PrepareResponse {
+                        session,
+                        message,
+                        issuer_attributes,
+                    };
+                    Ok((200, serde_json::to_vec(&response).unwrap()))
+                }
+                PRESIGN_PATH => {
+                    let request: PresignRequest = serde_json::from_slice(&body).unwrap();
+                    match self.sessions.borrow_mut().remove(&request.session) {
+                        Some(state) => {
+                            let presignature = self.key.compute_presignature(state, &request.challenge).unwrap();
+                            Ok((200, serde_json::to_vec(&PresignResponse { presignature }).unwrap()))
+                        }
+                        None => Ok((404, Vec::new())),
+                    }
+                }
+                _ => Ok((404, Vec::new())),
+            }
+        }
+    }
+
+    fn schema() -> Schema {
+        Schema::new(&["user id", "name", "expiry"])
+    }
+
+    fn client() -> IssuanceClient<Issuer> {
+        let issuer = Issuer::new();
+        let key = VerifyingKey::from(&issuer.key);
+        IssuanceClient::new(issuer, key, schema()).with_retries(2, Duration::ZERO)
+    }
+
+    fn issue(client: &IssuanceClient<Issuer>) -> Result<Token, ClientError> {
+        let opening = Opening::new(&mut OsRng, vec![Scalar::from(1234u16), Scalar::from(7u8), Scalar::ZERO]);
+        client.issue(&mut OsRng, opening, &[1], &[2], &[0u8; 64])
+    }
+
+    #[test]
+    fn retries_transient_failures() {
+        let client = client();
+
+        client.transport.failures.set(2);
+        let token = issue(&client).unwrap();
+        assert_eq!(token.opening.attributes[2], Scalar::from(2030u16));
+        assert_eq!(
+            client
+                .params
+                .key
+                .verify_prehashed(&token.hashed_message, &token.blinded_commitment, &token.signature),
+            Ok(())
+        );
+        assert_eq!(client.transport.requests.get(), 4);
+
+        client.transport.failures.set(3);
+        assert_eq!(issue(&client).unwrap_err(), ClientError::Status { code: 503 });
+    }
+
+    #[test]
+    fn restarts_expired_sessions() {
+        let client = client();
+
+        client.transport.expired.set(2);
+        assert!(issue(&client).is_ok());
+        assert_eq!(client.transport.requests.get(), 6);
+
+        client.transport.expired.set(3);
+        assert_eq!(issue(&client).unwrap_err(), ClientError::Status { code: 404 });
+    }
+
+    #[test]
+    fn malformed_prepare_responses_are_rejected() {
+        let client = client();
+
+        client.transport.malformed.set(true);
+        assert_eq!(issue(&client).unwrap_err(), ClientError::Format);
+        assert_eq!(client.transport.requests.get(), 1);
+    }
+
+    #[test]
+    fn backoff_is_capped() {
+        let client = client().with_retries(64, Duration::from_millis(100));
+
+        assert_eq!(client.delay(0), Duration::from_millis(100));
+        assert_eq!(client.delay(3), Duration::from_millis(800));
+        assert_eq!(client.delay(12), MAX_BACKOFF);
+        assert_eq!(client.delay(40), MAX_BACKOFF);
+    }
+}
+
+#[cfg(all(test, feature = "rocket"))]
+mod rocket_tests {
+    use super::*;
+    use crate::endpoints::{self, IssuerConfig};
+    use crate::signing::SigningKey;
+    use curve25519_dalek::scalar::Scalar;
+    use rand_core::OsRng;
+    use rocket::local::Client;
+    use rocket::Request;
+    use std::cell::Cell;
+
+    fn schema() -> Schema {
+        Schema::new(&["user id", "name", "expiry"])
+    }
+
+    fn authenticate(request: &Request) -> Option<Vec<(usize, Scalar)>> {
+        match request.headers().get_one("Authorization") {
+            Some("alice") => Some(vec![(1, Scalar::from(7u8)), (2, Scalar::from(2030u16))]),
+            _ => None,
+        }
+    }
+
+    // Flaky answers the first few requests with 503 before passing them on
+    struct Flaky {
+        client: Client,
+        failures: Cell<u32>,
+    }
+
+    impl Transport for Flaky {
+        fn post(&self, path: &str, headers: &[(&str, &str)], body: Vec<u8>) -> Result<(u16, Vec<u8>), ClientError> {
+            if self.failures.get() > 0 {
+                self.failures.set(self.failures.get() - 1);
+                return Ok((503, Vec::new()));
+            }
+            Transport::post(&self.client, path, headers, body)
+        }
+    }
+
+    fn server(signing_key: SigningKey) -> Client {
+        let config = IssuerConfig::new(signing_key, schema(), authenticate);
+        Client::new(rocket::ignite().manage(config).mount("/", endpoints::routes())).unwrap()
+    }
+
+    fn opening() -> Opening {
+        Opening::new(&mut OsRng, vec![Scalar::from(1234u16), Scalar::from(7u8), Scalar::ZERO])
+    }
+
+    #[test]
+    fn issues_through_an_in_process_server() {
+        let signing_key = SigningKey::from_bytes(&[7u8; 32]);
+        let key = VerifyingKey::from(&signing_key);
+        let transport = Flaky {
+            client: server(signing_key),
+            failures: Cell::new(2),
+        };
+        let client = IssuanceClient::new(transport, key, schema())
+            .with_authorization("alice")
+            .with_retries(2, Duration::ZERO);
+
+        let token = client.issue(&mut OsRng, opening(), &[1], &[2], &[0u8; 64]).unwrap();
+        assert_eq!(token.opening.attributes[2], Scalar::from(2030u16));
+        assert_eq!(
+            key.verify_prehashed(&token.hashed_message, &token.blinded_commitment, &token.signature),
+            Ok(())
+        );
+
+        // out of retries
+        client.transport.failures.set(3);
+        assert_eq!(
+            client.issue(&mut OsRng, opening(), &[1], &[2], &[0u8; 64]).unwrap_err(),
+            ClientError::Status { code: 503 }
+        );
+    }
+
+    #[test]
+    fn refusals_are_not_retried() {
+        let signing_key = SigningKey::from_bytes(&[7u8; 32]);
+        let key = VerifyingKey::from(&signing_key);
+        let client = IssuanceClient::new(server(signing_key), key, schema()).with_authorization("mallory");
+
+        assert_eq!(
+            client.issue(&mut OsRng, opening(), &[1], &[2], &[0u8; 64]).unwrap_err(),
+            ClientError::Status { code: 401 }
+        );
+    }
+}
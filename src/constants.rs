@@ -1,12 +1,19 @@
 use std::sync::OnceLock;
 
-use curve25519_dalek::ristretto::RistrettoPoint;
+use curve25519_dalek::ristretto::{RistrettoBasepointTable, RistrettoPoint};
 use curve25519_dalek::scalar::Scalar;
 
 use sha2::Sha512;
 
 pub const SECRET_KEY_LENGTH: usize = 32;
 
+// Every token in a batch is its own signing session, and all of them are open
+// at the same time. ACL is only proven secure for sequential sessions, and
+// ROS-style attacks get cheaper as the number of concurrent sessions grows, so
+// an issuer should count a whole batch against its concurrency limit. This caps
+// a single batch.
+pub const MAX_BATCH_SIZE: usize = 128;
+
 // nothing-up-my-sleeve generation of another generator as H=SHA512(G)
 // TODO: should probably be pub(crate)
 pub fn gen_h() -> &'static RistrettoPoint {
@@ -26,3 +33,18 @@ pub fn gen_z() -> &'static RistrettoPoint {
     GENERATOR_Z
         .get_or_init(|| RistrettoPoint::hash_from_bytes::<Sha512>(gen_h().compress().as_bytes()))
 }
+
+// precomputed multiples of H, so that fixed-base multiplications by H are as
+// cheap as RistrettoPoint::mul_base is for G
+pub fn gen_h_table() -> &'static RistrettoBasepointTable {
+    static TABLE_H: OnceLock<RistrettoBasepointTable> = OnceLock::new();
+
+    TABLE_H.get_or_init(|| RistrettoBasepointTable::create(gen_h()))
+}
+
+// precomputed multiples of Z, see gen_h_table
+pub fn gen_z_table() -> &'static RistrettoBasepointTable {
+    static TABLE_Z: OnceLock<RistrettoBasepointTable> = OnceLock::new();
+
+    TABLE_Z.get_or_init(|| RistrettoBasepointTable::create(gen_z()))
+}
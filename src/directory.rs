@@ -0,0 +1,400 @@
+use crate::errors::{SigningError, VerifyingError};
+use crate::proof::{Proof, Statement, Transcript};
+use crate::signing::SigningKey;
+use crate::threshold::{ShareChallenge, ThresholdCoordinator};
+use crate::verifying::VerifyingKey;
+
+use curve25519_dalek::ristretto::RistrettoPoint;
+use curve25519_dalek::scalar::Scalar;
+
+use rand_core::{CryptoRng, RngCore};
+
+use serde::{Deserialize, Serialize};
+
+// An issuer that hands different users credentials under different keys can
+// tell them apart by key when they present, however well the presentations
+// hide everything else. Clients defend against this by only accepting keys
+// from a published KeyDirectory, signed by a DirectoryKey that all clients
+// know, and checking every key they are served against it (check_key).
+// Clients also remember the latest epoch they accepted, so an issuer can't
+// serve a user an old directory that still lists a key since replaced.
+//
+// Every key in the directory carries a KeyProof, a proof of possession of the
+// signing scalar, so that nobody can list someone else's key as their own. A
+// threshold key's nodes prove possession together, through the coordinator
+// (see ThresholdCoordinator::possession_challenge).
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct KeyProof {
+    pub(crate) proof: Proof,
+}
+
+// DirectoryKey signs key directories. Like a RevocationKey, it must be
+// separate from every issuer's SigningKey.
+#[derive(Clone)]
+pub struct DirectoryKey {
+    pub(crate) scalar: Scalar,
+}
+
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct DirectoryVerifyingKey {
+    pub(crate) point: RistrettoPoint,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct DirectoryEntry {
+    pub issuer: String,
+    pub key: VerifyingKey,
+    pub possession: KeyProof,
+}
+
+// PossessionState is the coordinator's state for one threshold proof of
+// possession
+pub struct PossessionState {
+    nonces: Vec<(u32, RistrettoPoint)>,
+    challenge: Scalar,
+}
+
+// KeyDirectory is the signed list of issuer keys for one epoch
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct KeyDirectory {
+    pub epoch: u64,
+    pub entries: Vec<DirectoryEntry>,
+    pub(crate) proof: Proof,
+}
+
+impl SigningKey {
+    // prove_possession proves knowledge of the signing scalar, bound to the
+    // verifying key and to the issuer's name
+    pub fn prove_possession<R: RngCore + CryptoRng>(&self, rng: &mut R, issuer: &str) -> KeyProof {
+        let key = VerifyingKey::from(self);
+
+        KeyProof {
+            proof: Statement::discrete_log(key.point, self.scalar).prove(rng, possession_transcript(&key, issuer)),
+        }
+    }
+}
+
+impl ThresholdCoordinator {
+    // possession_challenge starts a proof of possession of the joint key. The
+    // nodes commit with KeyShare::commit, as for signing, and answer the
+    // returned challenge with KeyShare::respond; prove_possession combines
+    // their responses. A node's nonce must only ever answer one challenge.
+    pub fn possession_challenge(
+        &self,
+        issuer: &str,
+        nonces: &[(u32, RistrettoPoint)],
+    ) -> Result<(PossessionState, ShareChallenge), SigningError> {
+        let signers = self.signers(nonces)?;
+
+        let key = self.verifying_key();
+        let commitment = nonces.iter().map(|(_, nonce)| nonce).sum();
+        let challenge = Statement::discrete_log(key.point, Scalar::ZERO)
+            .challenge_for(possession_transcript(&key, issuer), &[commitment]);
+
+        // a node answers c with u_i - c*l_i*x_i, and the proof's response is
+        // sum(u_i) + challenge*x
+        Ok((
+            PossessionState {
+                nonces: nonces.to_vec(),
+                challenge,
+            },
+            ShareChallenge { c: -challenge, signers },
+        ))
+    }
+
+    // prove_possession checks every node's response against its public share
+    // and combines them into the key's KeyProof. responses must be in the
+    // order of the nonces given to possession_challenge.
+    pub fn prove_possession(&self, state: PossessionState, responses: &[Scalar]) -> Result<KeyProof, SigningError> {
+        self.check_responses(&state.nonces, &-state.challenge, responses)?;
+
+        Ok(KeyProof {
+            proof: Proof {
+                challenge: state.challenge,
+                responses: vec![responses.iter().sum()],
+                alternatives: Vec::new(),
+            },
+        })
+    }
+}
+
+impl VerifyingKey {
+    pub fn verify_possession(&self, issuer: &str, proof: &KeyProof) -> Result<(), VerifyingError> {
+        if Statement::discrete_log(self.point, Scalar::ZERO).verify(possession_transcript(self, issuer), &proof.proof) {
+            Ok(())
+        } else {
+            Err(VerifyingError::InvalidProof)
+        }
+    }
+}
+
+impl DirectoryKey {
+    pub fn random<R: RngCore + CryptoRng>(rng: &mut R) -> DirectoryKey {
+        DirectoryKey {
+            scalar: Scalar::random(rng),
+        }
+    }
+
+    pub fn verifying_key(&self) -> DirectoryVerifyingKey {
+        DirectoryVerifyingKey {
+            point: RistrettoPoint::mul_base(&self.scalar),
+        }
+    }
+
+    // sign signs a directory after checking every entry's proof of
+    // possession. Issuer names must be unique.
+    pub fn sign<R: RngCore + CryptoRng>(
+        &self,
+        rng: &mut R,
+        epoch: u64,
+        entries: Vec<DirectoryEntry>,
+    ) -> Result<KeyDirectory, VerifyingError> {
+        for (position, entry) in entries.iter().enumerate() {
+            entry.key.verify_possession(&entry.issuer, &entry.possession)?;
+
+            if entries[..position].iter().any(|other| other.issuer == entry.issuer) {
+                return Err(VerifyingError::DuplicateIssuer);
+            }
+        }
+
+        let key = self.verifying_key();
+        let mut directory = KeyDirectory {
+            epoch,
+            entries,
+            proof: Proof::default(),
+        };
+
+        directory.proof = Statement::discrete_log(key.point, self.scalar).prove(rng, directory.transcript(&key));
+        Ok(directory)
+    }
+}
+
+impl KeyDirectory {
+    // verify checks the directory's signature and every entry's proof of
+    // possession, and that issuer names are unique as in DirectoryKey::sign
+    pub fn verify(&self, key: &DirectoryVerifyingKey) -> Result<(), VerifyingError> {
+        if !Statement::discrete_log(key.point, Scalar::ZERO).verify(self.transcript(key), &self.proof) {
+            return Err(VerifyingError::InvalidProof);
+        }
+
+        for (position, entry) in self.entries.iter().enumerate() {
+            entry.key.verify_possession(&entry.issuer, &entry.possession)?;
+
+            if self.entries[..position].iter().any(|other| other.issuer == entry.issuer) {
+                return Err(VerifyingError::DuplicateIssuer);
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn lookup(&self, issuer: &str) -> Option<&VerifyingKey> {
+        self.entries
+            .iter()
+            .find(|entry| entry.issuer == issuer)
+            .map(|entry| &entry.key)
+    }
+
+    // check_key is the key-consistency check: it verifies the directory,
+    // fails with StaleDirectory if its epoch is before min_epoch (the epoch
+    // of the last directory the client accepted) and with UnexpectedKey
+    // unless served is the key it lists for issuer
+    pub fn check_key(
+        &self,
+        key: &DirectoryVerifyingKey,
+        min_epoch: u64,
+        issuer: &str,
+        served: &VerifyingKey,
+    ) -> Result<(), VerifyingError> {
+        self.verify(key)?;
+
+        if self.epoch < min_epoch {
+            return Err(VerifyingError::StaleDirectory);
+        }
+
+        match self.lookup(issuer) {
+            Some(listed) if listed == served => Ok(()),
+            _ => Err(VerifyingError::UnexpectedKey),
+        }
+    }
+
+    fn transcript(&self, key: &DirectoryVerifyingKey) -> Transcript {
+        let mut transcript = Transcript::new(b"acl key directory");
+        transcript.append_point(b"key", &key.point);
+        transcript.append_message(b"epoch", &self.epoch.to_le_bytes());
+        for entry in self.entries.iter() {
+            transcript.append_message(b"issuer", entry.issuer.as_bytes());
+            transcript.append_point(b"issuer key", &entry.key.point);
+        }
+        transcript
+    }
+}
+
+fn possession_transcript(key: &VerifyingKey, issuer: &str) -> Transcript {
+    let mut transcript = Transcript::new(b"acl key possession");
+    transcript.append_message(b"issuer", issuer.as_bytes());
+    transcript.append_point(b"key", &key.point);
+    transcript
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand_core::OsRng;
+
+    fn entry(issuer: &str, signing_key: &SigningKey) -> DirectoryEntry {
+        DirectoryEntry {
+            issuer: issuer.to_string(),
+            key: VerifyingKey::from(signing_key),
+            possession: signing_key.prove_possession(&mut OsRng, issuer),
+        }
+    }
+
+    #[test]
+    fn served_keys_must_match_the_directory() {
+        let news = SigningKey::from_bytes(&[7u8; 32]);
+        let sports = SigningKey::from_bytes(&[8u8; 32]);
+        let tagging = SigningKey::from_bytes(&[9u8; 32]);
+        let directory_key = DirectoryKey::random(&mut OsRng);
+
+        let directory = directory_key
+            .sign(&mut OsRng, 1, vec![entry("news", &news), entry("sports", &sports)])
+            .unwrap();
+        let key = directory_key.verifying_key();
+
+        assert_eq!(directory.check_key(&key, 1, "news", &VerifyingKey::from(&news)), Ok(()));
+        assert_eq!(
+            directory.check_key(&key, 1, "news", &VerifyingKey::from(&tagging)),
+            Err(VerifyingError::UnexpectedKey)
+        );
+        assert_eq!(
+            directory.check_key(&key, 1, "news", &VerifyingKey::from(&sports)),
+            Err(VerifyingError::UnexpectedKey)
+        );
+        assert_eq!(
+            directory.check_key(&key, 1, "weather", &VerifyingKey::from(&news)),
+            Err(VerifyingError::UnexpectedKey)
+        );
+
+        let mut swapped = directory.clone();
+        swapped.entries[0].key = VerifyingKey::from(&tagging);
+        assert_eq!(
+            swapped.check_key(&key, 1, "news", &VerifyingKey::from(&tagging)),
+            Err(VerifyingError::InvalidProof)
+        );
+        assert_eq!(
+            directory.check_key(
+                &DirectoryKey::random(&mut OsRng).verifying_key(),
+                1,
+                "news",
+                &VerifyingKey::from(&news)
+            ),
+            Err(VerifyingError::InvalidProof)
+        );
+
+        // a client that has seen epoch 2 refuses the epoch 1 directory
+        assert_eq!(
+            directory.check_key(&key, 2, "news", &VerifyingKey::from(&news)),
+            Err(VerifyingError::StaleDirectory)
+        );
+    }
+
+    #[test]
+    fn verify_refuses_duplicate_issuers() {
+        let news = SigningKey::from_bytes(&[7u8; 32]);
+        let tagging = SigningKey::from_bytes(&[9u8; 32]);
+        let directory_key = DirectoryKey::random(&mut OsRng);
+        let key = directory_key.verifying_key();
+
+        // signed without going through DirectoryKey::sign's checks
+        let mut directory = KeyDirectory {
+            epoch: 1,
+            entries: vec![entry("news", &news), entry("news", &tagging)],
+            proof: Proof::default(),
+        };
+        directory.proof =
+            Statement::discrete_log(key.point, directory_key.scalar).prove(&mut OsRng, directory.transcript(&key));
+
+        assert_eq!(directory.verify(&key), Err(VerifyingError::DuplicateIssuer));
+        assert_eq!(
+            directory.check_key(&key, 1, "news", &VerifyingKey::from(&tagging)),
+            Err(VerifyingError::DuplicateIssuer)
+        );
+    }
+
+    #[test]
+    fn possession_proofs_bind_key_and_issuer() {
+        let news = SigningKey::from_bytes(&[7u8; 32]);
+        let sports = SigningKey::from_bytes(&[8u8; 32]);
+        let proof = news.prove_possession(&mut OsRng, "news");
+
+        assert_eq!(VerifyingKey::from(&news).verify_possession("news", &proof), Ok(()));
+        assert_eq!(
+            VerifyingKey::from(&news).verify_possession("sports", &proof),
+            Err(VerifyingError::InvalidProof)
+        );
+        assert_eq!(
+            VerifyingKey::from(&sports).verify_possession("news", &proof),
+            Err(VerifyingError::InvalidProof)
+        );
+
+        // the directory refuses a key listed without possession
+        let mut stolen = entry("impostor", &sports);
+        stolen.key = VerifyingKey::from(&news);
+        assert_eq!(
+            DirectoryKey::random(&mut OsRng).sign(&mut OsRng, 1, vec![stolen]).unwrap_err(),
+            VerifyingError::InvalidProof
+        );
+        assert_eq!(
+            DirectoryKey::random(&mut OsRng)
+                .sign(&mut OsRng, 1, vec![entry("news", &news), entry("news", &sports)])
+                .unwrap_err(),
+            VerifyingError::DuplicateIssuer
+        );
+    }
+
+    #[test]
+    fn threshold_keys_prove_possession() {
+        let shares = SigningKey::from_bytes(&[7u8; 32]).split(&mut OsRng, 2, 3).unwrap();
+        let coordinator = ThresholdCoordinator::new(shares[0].public_shares().clone());
+        let key = coordinator.verifying_key();
+
+        let prove = |signers: &[usize], corrupt: bool| {
+            let (nonces, points): (Vec<_>, Vec<_>) = signers
+                .iter()
+                .map(|signer| {
+                    let (nonce, point) = shares[*signer].commit(&mut OsRng);
+                    (nonce, (shares[*signer].index(), point))
+                })
+                .unzip();
+            let (state, challenge) = coordinator.possession_challenge("news", &points)?;
+            let mut responses: Vec<Scalar> = signers
+                .iter()
+                .zip(nonces)
+                .map(|(signer, nonce)| shares[*signer].respond(nonce, &challenge).unwrap())
+                .collect();
+            if corrupt {
+                responses[0] += Scalar::ONE;
+            }
+            coordinator.prove_possession(state, &responses)
+        };
+
+        let proof = prove(&[0, 2], false).unwrap();
+        assert_eq!(key.verify_possession("news", &proof), Ok(()));
+        assert_eq!(key.verify_possession("sports", &proof), Err(VerifyingError::InvalidProof));
+        assert!(DirectoryKey::random(&mut OsRng)
+            .sign(
+                &mut OsRng,
+                1,
+                vec![DirectoryEntry {
+                    issuer: "news".to_string(),
+                    key,
+                    possession: proof,
+                }]
+            )
+            .is_ok());
+
+        assert_eq!(prove(&[1], false).unwrap_err(), SigningError::Threshold);
+        assert_eq!(prove(&[0, 1], true).unwrap_err(), SigningError::InvalidShare { index: 1 });
+    }
+}
@@ -0,0 +1,462 @@
+use crate::errors::SigningError;
+use crate::proof::{Proof, Statement, Transcript};
+use crate::threshold::{evaluate, KeyShare, PublicShares};
+
+use curve25519_dalek::ristretto::RistrettoPoint;
+use curve25519_dalek::scalar::Scalar;
+use curve25519_dalek::traits::VartimeMultiscalarMul;
+
+use rand_core::{CryptoRng, RngCore};
+
+use serde::{Deserialize, Serialize};
+
+use std::collections::{BTreeMap, BTreeSet};
+
+// Distributed key generation for threshold issuance, so that no party ever
+// holds the whole signing scalar. This is Pedersen's DKG with Feldman
+// commitments, where every dealer also proves knowledge of its constant term
+// (as in FROST) so that nobody can pick its contribution after seeing the
+// others'. Participants are numbered 1..=participants and run three rounds:
+//
+//  1. every participant broadcasts its DkgCommitment and sends each other
+//     participant its DkgShare over a private, authenticated channel
+//  2. every participant checks what it got (receive) and broadcasts a
+//     Complaint against each dealer whose share is missing or wrong
+//  3. accused dealers answer by publishing the disputed share (answer), and
+//     everyone disqualifies dealers with a bad commitment, or a missing or
+//     wrong answer (finish)
+//
+// The joint key is the sum of the qualified dealers' constant terms, and each
+// participant's share is the sum of the shares it got from them. All
+// broadcasts must reach every participant unchanged, so that everyone
+// disqualifies the same dealers, and must be authenticated: answer and finish
+// take every complaint together with the participant that broadcast it.
+pub struct DkgParticipant {
+    index: u32,
+    threshold: u32,
+    participants: u32,
+    coefficients: Vec<Scalar>,
+    context: Vec<u8>,
+    commitments: BTreeMap<u32, Vec<RistrettoPoint>>,
+    shares: BTreeMap<u32, Scalar>,
+    disqualified: BTreeSet<u32>,
+}
+
+// DkgCommitment is a dealer's broadcast: its polynomial's coefficients times
+// G, and a proof of knowledge of the constant term
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct DkgCommitment {
+    pub sender: u32,
+    pub coefficients: Vec<RistrettoPoint>,
+    pub(crate) proof: Proof,
+}
+
+// DkgShare is the dealer's polynomial evaluated at the receiver's index. It is
+// secret, and must only travel encrypted to the receiver.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct DkgShare {
+    pub sender: u32,
+    pub receiver: u32,
+    pub value: Scalar,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Complaint {
+    pub accuser: u32,
+    pub accused: u32,
+}
+
+// ComplaintAnswer publishes the share a complaint was about
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct ComplaintAnswer {
+    pub accuser: u32,
+    pub accused: u32,
+    pub value: Scalar,
+}
+
+impl DkgParticipant {
+    // new starts participant index's part of a DKG run, returning its
+    // commitment to broadcast. context must be unique to the run (e.g. the
+    // issuer's name and a date), so that messages can't be replayed across
+    // runs.
+    pub fn new<R: RngCore + CryptoRng>(
+        rng: &mut R,
+        index: u32,
+        threshold: u32,
+        participants: u32,
+        context: &[u8],
+    ) -> Result<(DkgParticipant, DkgCommitment), SigningError> {
+        if threshold == 0 || threshold > participants || index == 0 || index > participants {
+            return Err(SigningError::Threshold);
+        }
+
+        let coefficients: Vec<Scalar> = (0..threshold).map(|_| Scalar::random(rng)).collect();
+        let points: Vec<RistrettoPoint> = coefficients.iter().map(RistrettoPoint::mul_base).collect();
+
+        let proof = Statement::discrete_log(points[0], coefficients[0]).prove(rng, transcript(context, index, &points));
+
+        let participant = DkgParticipant {
+            index,
+            threshold,
+            participants,
+            context: context.to_vec(),
+            commitments: BTreeMap::from([(index, points.clone())]),
+            shares: BTreeMap::from([(index, evaluate(&coefficients, index))]),
+            coefficients,
+            disqualified: BTreeSet::new(),
+        };
+
+        Ok((
+            participant,
+            DkgCommitment {
+                sender: index,
+                coefficients: points,
+                proof,
+            },
+        ))
+    }
+
+    // deal returns the share for every other participant
+    pub fn deal(&self) -> Vec<DkgShare> {
+        (1..=self.participants)
+            .filter(|receiver| *receiver != self.index)
+            .map(|receiver| DkgShare {
+                sender: self.index,
+                receiver,
+                value: evaluate(&self.coefficients, receiver),
+            })
+            .collect()
+    }
+
+    // receive takes every other dealer's broadcast commitment and the shares
+    // sent to this participant, and returns the complaints to broadcast.
+    // Dealers without a valid commitment are disqualified right away.
+    pub fn receive(&mut self, commitments: &[DkgCommitment], shares: &[DkgShare]) -> Vec<Complaint> {
+        let mut complaints = Vec::new();
+
+        for sender in (1..=self.participants).filter(|sender| *sender != self.index) {
+            let commitment = commitments.iter().find(|commitment| commitment.sender == sender);
+            let valid = commitment.is_some_and(|commitment| {
+                commitment.coefficients.len() == self.threshold as usize
+                    && Statement::discrete_log(commitment.coefficients[0], Scalar::ZERO)
+                        .verify(transcript(&self.context, sender, &commitment.coefficients), &commitment.proof)
+            });
+
+            match commitment {
+                Some(commitment) if valid => {
+                    self.commitments.insert(sender, commitment.coefficients.clone());
+                }
+                _ => {
+                    self.disqualified.insert(sender);
+                    continue;
+                }
+            }
+
+            let share = shares
+                .iter()
+                .find(|share| share.sender == sender && share.receiver == self.index);
+            match share {
+                Some(share) if self.check(sender, self.index, &share.value) => {
+                    self.shares.insert(sender, share.value);
+                }
+                _ => complaints.push(Complaint {
+                    accuser: self.index,
+                    accused: sender,
+                }),
+            }
+        }
+
+        complaints
+    }
+
+    // answer publishes the disputed shares for the complaints against this
+    // participant, given as (sender, complaint)
+    pub fn answer(&self, complaints: &[(u32, Complaint)]) -> Vec<ComplaintAnswer> {
+        complaints
+            .iter()
+            .filter(|(sender, complaint)| complaint.accused == self.index && self.admissible(*sender, complaint))
+            .map(|(_, complaint)| ComplaintAnswer {
+                accuser: complaint.accuser,
+                accused: self.index,
+                value: evaluate(&self.coefficients, complaint.accuser),
+            })
+            .collect()
+    }
+
+    // finish resolves all broadcast complaints, given as (sender, complaint),
+    // and answers, and returns this participant's key share. It fails if fewer
+    // than threshold dealers remain qualified.
+    pub fn finish(
+        mut self,
+        complaints: &[(u32, Complaint)],
+        answers: &[ComplaintAnswer],
+    ) -> Result<KeyShare, SigningError> {
+        for (sender, complaint) in complaints {
+            if !self.admissible(*sender, complaint)
+                || self.disqualified.contains(&complaint.accused)
+                || !self.commitments.contains_key(&complaint.accused)
+            {
+                continue;
+            }
+
+            let answer = answers.iter().find(|answer| {
+                answer.accuser == complaint.accuser && answer.accused == complaint.accused
+            });
+            match answer {
+                Some(answer) if self.check(answer.accused, answer.accuser, &answer.value) => {
+                    if answer.accuser == self.index {
+                        self.shares.insert(answer.accused, answer.value);
+                    }
+                }
+                _ => {
+                    self.disqualified.insert(complaint.accused);
+                }
+            }
+        }
+
+        let qualified: Vec<u32> = self
+            .commitments
+            .keys()
+            .filter(|dealer| !self.disqualified.contains(dealer))
+            .copied()
+            .collect();
+        if qualified.len() < self.threshold as usize || qualified.iter().any(|dealer| !self.shares.contains_key(dealer))
+        {
+            return Err(SigningError::Threshold);
+        }
+
+        let key = qualified.iter().map(|dealer| self.commitments[dealer][0]).sum();
+        let shares = (1..=self.participants)
+            .map(|receiver| {
+                qualified
+                    .iter()
+                    .map(|dealer| evaluate_in_exponent(&self.commitments[dealer], receiver))
+                    .sum()
+            })
+            .collect();
+
+        Ok(KeyShare {
+            index: self.index,
+            scalar: qualified.iter().map(|dealer| self.shares[dealer]).sum(),
+            public: PublicShares {
+                threshold: self.threshold,
+                key,
+                shares,
+            },
+        })
+    }
+
+    // admissible checks that a complaint was broadcast by its accuser, a
+    // participant other than the accused. An answer to any other complaint
+    // would publish a share that isn't the sender's to ask for: for accuser 0
+    // it is the dealer's constant term, for another participant their share.
+    fn admissible(&self, sender: u32, complaint: &Complaint) -> bool {
+        sender == complaint.accuser
+            && complaint.accuser != complaint.accused
+            && (1..=self.participants).contains(&complaint.accuser)
+    }
+
+    // check verifies a dealer's share for receiver against the dealer's
+    // commitment, as value*G = sum(C_k * receiver^k)
+    fn check(&self, dealer: u32, receiver: u32, value: &Scalar) -> bool {
+        self.commitments
+            .get(&dealer)
+            .is_some_and(|commitment| RistrettoPoint::mul_base(value) == evaluate_in_exponent(commitment, receiver))
+    }
+}
+
+fn evaluate_in_exponent(commitment: &[RistrettoPoint], x: u32) -> RistrettoPoint {
+    let x = Scalar::from(x);
+    let powers: Vec<Scalar> = commitment
+        .iter()
+        .scan(Scalar::ONE, |power, _| {
+            let current = *power;
+            *power *= x;
+            Some(current)
+        })
+        .collect();
+
+    RistrettoPoint::vartime_multiscalar_mul(powers, commitment)
+}
+
+fn transcript(context: &[u8], sender: u32, coefficients: &[RistrettoPoint]) -> Transcript {
+    let mut transcript = Transcript::new(b"acl dkg commitment");
+    transcript.append_message(b"context", context);
+    transcript.append_message(b"sender", &sender.to_le_bytes());
+    for coefficient in coefficients {
+        transcript.append_point(b"coefficient", coefficient);
+    }
+    transcript
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::threshold::{lagrange, ThresholdCoordinator};
+    use crate::user::UserParameters;
+    use curve25519_dalek::constants::RISTRETTO_BASEPOINT_POINT;
+    use rand_core::OsRng;
+
+    // run runs a DKG among participants, applying tamper to the round 1
+    // messages and dropping the answers of the dealers in silent
+    fn run(
+        threshold: u32,
+        participants: u32,
+        tamper: impl Fn(&mut Vec<DkgCommitment>, &mut Vec<DkgShare>),
+        silent: &[u32],
+    ) -> (Vec<DkgCommitment>, Vec<Result<KeyShare, SigningError>>) {
+        let (dkg, mut commitments): (Vec<DkgParticipant>, Vec<DkgCommitment>) = (1..=participants)
+            .map(|index| DkgParticipant::new(&mut OsRng, index, threshold, participants, b"test run").unwrap())
+            .unzip();
+        let mut shares: Vec<DkgShare> = dkg.iter().flat_map(|participant| participant.deal()).collect();
+        tamper(&mut commitments, &mut shares);
+
+        let mut dkg = dkg;
+        let complaints: Vec<(u32, Complaint)> = dkg
+            .iter_mut()
+            .flat_map(|participant| {
+                let sender = participant.index;
+                participant
+                    .receive(&commitments, &shares)
+                    .into_iter()
+                    .map(move |complaint| (sender, complaint))
+            })
+            .collect();
+        let answers: Vec<ComplaintAnswer> = dkg
+            .iter()
+            .filter(|participant| !silent.contains(&participant.index))
+            .flat_map(|participant| participant.answer(&complaints))
+            .collect();
+
+        let results = dkg
+            .into_iter()
+            .map(|participant| participant.finish(&complaints, &answers))
+            .collect();
+        (commitments, results)
+    }
+
+    // assert_consistent checks that the signers agree on the public shares,
+    // and that their shares interpolate to the secret behind the joint key
+    fn assert_consistent(results: &[Result<KeyShare, SigningError>], signers: &[u32]) -> PublicShares {
+        let share = |signer: &u32| results[*signer as usize - 1].as_ref().ok().unwrap();
+        let public = share(&signers[0]).public_shares();
+        assert!(signers.iter().all(|signer| share(signer).public_shares() == public));
+
+        let secret: Scalar = signers
+            .iter()
+            .map(|signer| lagrange(signers, *signer, results.len()).unwrap() * share(signer).scalar)
+            .sum();
+        assert_eq!(RistrettoPoint::mul_base(&secret), public.key);
+        public.clone()
+    }
+
+    #[test]
+    fn dkg_produces_a_working_threshold_key() {
+        let (_, results) = run(3, 5, |_, _| {}, &[]);
+        assert_consistent(&results, &[1, 2, 3]);
+        assert_consistent(&results, &[2, 4, 5]);
+        let shares: Vec<KeyShare> = results.into_iter().map(Result::unwrap).collect();
+
+        // the shares sign like dealt ones
+        let coordinator = ThresholdCoordinator::new(shares[0].public_shares().clone());
+        let user_params = UserParameters {
+            key: coordinator.verifying_key(),
+        };
+        let commitment = RistrettoPoint::random(&mut OsRng);
+
+        let (nonces, points): (Vec<_>, Vec<_>) = shares[1..4]
+            .iter()
+            .map(|share| {
+                let (nonce, point) = share.commit(&mut OsRng);
+                (nonce, (share.index(), point))
+            })
+            .unzip();
+        let (mut state, prepare_message) = coordinator.prepare(&mut OsRng, &commitment, &points).unwrap();
+        let (us, challenge) = user_params
+            .compute_challenge(&mut OsRng, &commitment, &[0u8; 64], &prepare_message)
+            .unwrap();
+        let share_challenge = coordinator.challenge(&mut state, &challenge).unwrap();
+        let responses: Vec<Scalar> = shares[1..4]
+            .iter()
+            .zip(nonces)
+            .map(|(share, nonce)| share.respond(nonce, &share_challenge).unwrap())
+            .collect();
+        let presignature = coordinator.presign(state, &responses).unwrap();
+        let credential = user_params.compute_signature(&us, &presignature).unwrap();
+
+        assert_eq!(
+            credential.verify(&coordinator.verifying_key()),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn complaints_disqualify_misbehaving_dealers() {
+        // dealer 2 sends participant 4 a bad share but answers the complaint;
+        // dealer 3 does the same to participant 5 and stays silent; dealer 5's
+        // proof of its constant term is broken
+        let (commitments, results) = run(
+            3,
+            5,
+            |commitments, shares| {
+                for share in shares.iter_mut() {
+                    if (share.sender, share.receiver) == (2, 4) || (share.sender, share.receiver) == (3, 5) {
+                        share.value += Scalar::ONE;
+                    }
+                }
+                commitments[4].coefficients[1] += RISTRETTO_BASEPOINT_POINT;
+            },
+            &[3],
+        );
+        // the misbehaving dealers' own views don't matter; the others agree,
+        // and only dealers 1, 2 and 4 contributed
+        let public = assert_consistent(&results, &[1, 2, 4]);
+        let key: RistrettoPoint = [0, 1, 3].iter().map(|dealer| commitments[*dealer].coefficients[0]).sum();
+        assert_eq!(public.key, key);
+    }
+
+    #[test]
+    fn inadmissible_complaints_get_no_answer() {
+        let (mut dkg, commitments): (Vec<DkgParticipant>, Vec<DkgCommitment>) = (1..=3)
+            .map(|index| DkgParticipant::new(&mut OsRng, index, 2, 3, b"test run").unwrap())
+            .unzip();
+        let shares: Vec<DkgShare> = dkg.iter().flat_map(|participant| participant.deal()).collect();
+        for participant in dkg.iter_mut() {
+            assert!(participant.receive(&commitments, &shares).is_empty());
+        }
+
+        // participant 3 asks for dealer 1's constant term, for participant 2's
+        // share, and for a share nobody holds; dealer 1 accuses itself
+        let complaints = [
+            (3, Complaint { accuser: 0, accused: 1 }),
+            (3, Complaint { accuser: 2, accused: 1 }),
+            (3, Complaint { accuser: 4, accused: 1 }),
+            (1, Complaint { accuser: 1, accused: 1 }),
+        ];
+        assert!(dkg[0].answer(&complaints).is_empty());
+
+        // and leaving them unanswered doesn't disqualify dealer 1
+        let results: Vec<Result<KeyShare, SigningError>> =
+            dkg.into_iter().map(|participant| participant.finish(&complaints, &[])).collect();
+        let public = assert_consistent(&results, &[1, 3]);
+        let key: RistrettoPoint = commitments.iter().map(|commitment| commitment.coefficients[0]).sum();
+        assert_eq!(public.key, key);
+    }
+
+    #[test]
+    fn dkg_fails_without_enough_qualified_dealers() {
+        let (_, results) = run(
+            3,
+            3,
+            |commitments, _| {
+                commitments[0].coefficients[0] += RISTRETTO_BASEPOINT_POINT;
+            },
+            &[],
+        );
+        assert!(results[1..].iter().all(|result| result.as_ref().err() == Some(&SigningError::Threshold)));
+        assert!(matches!(
+            DkgParticipant::new(&mut OsRng, 4, 3, 3, b""),
+            Err(SigningError::Threshold)
+        ));
+    }
+}
@@ -0,0 +1,357 @@
+// Ready-made issuance endpoints for Rocket, behind the "rocket" feature.
+//
+// The application manages an IssuerConfig with its signing key, the schema
+// it issues under and an Authenticator, and mounts routes():
+//
+//   rocket::ignite()
+//       .manage(IssuerConfig::new(signing_key, schema, authenticator))
+//       .mount("/", endpoints::routes())
+//
+// See the messages module for the wire format.
+//
+// Signer state stays on the server, keyed by a random session id, between
+// /acl/prepare and /acl/presign. It is removed as soon as it answers a
+// challenge: answering two challenges with one state reveals the signing key.
+// That is also why the state is never sealed and handed to the client, since
+// nothing would stop a client from sending it back twice.
+use crate::attributes::Schema;
+use crate::issuance::IssuanceRequest;
+use crate::messages::{PrepareResponse, PresignRequest, PresignResponse, SESSION_LENGTH};
+use crate::signing::{SignerState, SigningKey};
+
+use curve25519_dalek::scalar::Scalar;
+
+use rand_core::{OsRng, RngCore};
+
+use rocket::http::{ContentType, Status};
+use rocket::request::{self, FromRequest, Request};
+use rocket::response::content::Content;
+use rocket::{Data, Outcome, Route, State};
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use std::collections::HashMap;
+use std::io::Read;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// bodies are small: the largest is an IssuanceRequest, whose proof grows with
+// the number of hidden attributes
+const BODY_LIMIT: u64 = 64 * 1024;
+
+// Authenticator decides which attribute values the user behind a request may
+// receive, as (schema index, value), or refuses issuance with None. It would
+// typically check a session cookie or an Authorization header and look the
+// user up. Closures of the right type are Authenticators.
+//
+// The granted indices must be exactly those the IssuanceRequest reveals or
+// leaves to the issuer, and revealed values must match the granted ones.
+// Every other attribute stays hidden, i.e. is the user's own (e.g. a secret
+// user id).
+pub trait Authenticator: Send + Sync + 'static {
+    fn authenticate(&self, request: &Request) -> Option<Vec<(usize, Scalar)>>;
+}
+
+impl<F> Authenticator for F
+where
+    F: Fn(&Request) -> Option<Vec<(usize, Scalar)>> + Send + Sync + 'static,
+{
+    fn authenticate(&self, request: &Request) -> Option<Vec<(usize, Scalar)>> {
+        self(request)
+    }
+}
+
+// IssuerConfig is the managed state behind routes()
+pub struct IssuerConfig {
+    key: SigningKey,
+    schema: Schema,
+    authenticator: Box<dyn Authenticator>,
+    sessions: Mutex<HashMap<[u8; SESSION_LENGTH], Session>>,
+    max_sessions: usize,
+    session_lifetime: Duration,
+}
+
+struct Session {
+    state: SignerState,
+    expires: Instant,
+}
+
+// Grant is the Authenticator's decision for a request
+struct Grant(Vec<(usize, Scalar)>);
+
+// Format is whether a request's body (by Content-Type) and its response (by
+// Accept) are raw bytes rather than JSON
+struct Format {
+    binary_body: bool,
+    binary_reply: bool,
+}
+
+impl IssuerConfig {
+    pub fn new<A: Authenticator>(key: SigningKey, schema: Schema, authenticator: A) -> IssuerConfig {
+        IssuerConfig {
+            key,
+            schema,
+            authenticator: Box::new(authenticator),
+            sessions: Mutex::new(HashMap::new()),
+            max_sessions: 64,
+            session_lifetime: Duration::from_secs(60),
+        }
+    }
+
+    // with_session_limits bounds the number of sessions open at once (see
+    // MAX_BATCH_SIZE on concurrent sessions) and how long a session waits for
+    // its challenge. Prepare answers 503 while all sessions are taken.
+    pub fn with_session_limits(mut self, max_sessions: usize, session_lifetime: Duration) -> IssuerConfig {
+        self.max_sessions = max_sessions;
+        self.session_lifetime = session_lifetime;
+        self
+    }
+
+    fn prepare(&self, grant: &[(usize, Scalar)], request: &IssuanceRequest) -> Result<PrepareResponse, Status> {
+        let issuer_attributes = granted(grant, request).ok_or(Status::Forbidden)?;
+        request.verify(&self.schema).map_err(|_| Status::BadRequest)?;
+        let commitment = request
+            .certified_commitment(&self.schema, &issuer_attributes)
+            .map_err(|_| Status::BadRequest)?;
+
+        let mut session = [0u8; SESSION_LENGTH];
+        OsRng.fill_bytes(&mut session);
+
+        let mut sessions = self.sessions.lock().map_err(|_| Status::InternalServerError)?;
+        let now = Instant::now();
+        sessions.retain(|_, open| open.expires > now);
+        if sessions.len() >= self.max_sessions {
+            return Err(Status::ServiceUnavailable);
+        }
+
+        let (state, message) = self
+            .key
+            .prepare_hedged(&commitment, &session)
+            .map_err(|_| Status::InternalServerError)?;
+        sessions.insert(
+            session,
+            Session {
+                state,
+                expires: now + self.session_lifetime,
+            },
+        );
+
+        Ok(PrepareResponse {
+            session: session.to_vec(),
+            message,
+            issuer_attributes,
+        })
+    }
+
+    fn presign(&self, request: &PresignRequest) -> Result<PresignResponse, Status> {
+        let session: [u8; SESSION_LENGTH] = request.session.as_slice().try_into().map_err(|_| Status::BadRequest)?;
+        if request.challenge.len() != 32 {
+            return Err(Status::BadRequest);
+        }
+
+        let open = self
+            .sessions
+            .lock()
+            .map_err(|_| Status::InternalServerError)?
+            .remove(&session)
+            .filter(|open| open.expires > Instant::now())
+            .ok_or(Status::NotFound)?;
+
+        let presignature = self
+            .key
+            .compute_presignature(open.state, &request.challenge)
+            .map_err(|_| Status::BadRequest)?;
+
+        Ok(PresignResponse { presignature })
+    }
+}
+
+// granted checks a request against the grant and returns the issuer's values
+// for its issuer_assigned indices, in order
+fn granted(grant: &[(usize, Scalar)], request: &IssuanceRequest) -> Option<Vec<(usize, Scalar)>> {
+    let value = |index: &usize| grant.iter().find(|(granted, _)| granted == index).map(|(_, value)| *value);
+
+    let covered = grant.iter().all(|(index, _)| {
+        request.revealed.iter().any(|(revealed, _)| revealed == index) || request.issuer_assigned.contains(index)
+    });
+    if !covered || request.revealed.iter().any(|(index, revealed)| value(index) != Some(*revealed)) {
+        return None;
+    }
+
+    request
+        .issuer_assigned
+        .iter()
+        .map(|index| Some((*index, value(index)?)))
+        .collect()
+}
+
+impl<'a, 'r> FromRequest<'a, 'r> for Grant {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> request::Outcome<Grant, ()> {
+        let config = match request.guard::<State<IssuerConfig>>().succeeded() {
+            Some(config) => config,
+            None => return Outcome::Failure((Status::InternalServerError, ())),
+        };
+
+        match config.authenticator.authenticate(request) {
+            Some(grant) => Outcome::Success(Grant(grant)),
+            None => Outcome::Failure((Status::Unauthorized, ())),
+        }
+    }
+}
+
+impl<'a, 'r> FromRequest<'a, 'r> for Format {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> request::Outcome<Format, ()> {
+        Outcome::Success(Format {
+            binary_body: request.content_type().is_some_and(|content_type| content_type.is_binary()),
+            binary_reply: request
+                .accept()
+                .is_some_and(|accept| accept.preferred().media_type().is_binary()),
+        })
+    }
+}
+
+impl Format {
+    fn decode<T: DeserializeOwned>(&self, body: &[u8], from_bytes: fn(&[u8]) -> Option<T>) -> Result<T, Status> {
+        if self.binary_body {
+            from_bytes(body).ok_or(Status::BadRequest)
+        } else {
+            serde_json::from_slice(body).map_err(|_| Status::BadRequest)
+        }
+    }
+
+    fn reply<T: Serialize>(&self, message: &T, to_bytes: fn(&T) -> Vec<u8>) -> Content<Vec<u8>> {
+        if self.binary_reply {
+            Content(ContentType::Binary, to_bytes(message))
+        } else {
+            Content(
+                ContentType::JSON,
+                serde_json::to_vec(message).expect("messages always serialize"),
+            )
+        }
+    }
+}
+
+fn read(body: Data) -> Result<Vec<u8>, Status> {
+    let mut bytes = Vec::new();
+    body.open()
+        .take(BODY_LIMIT)
+        .read_to_end(&mut bytes)
+        .map_err(|_| Status::BadRequest)?;
+    Ok(bytes)
+}
+
+// an IssuanceRequest has no binary encoding, so prepare always takes JSON
+#[post("/acl/prepare", data = "<body>")]
+fn prepare(config: State<IssuerConfig>, grant: Grant, format: Format, body: Data) -> Result<Content<Vec<u8>>, Status> {
+    let request: IssuanceRequest = serde_json::from_slice(&read(body)?).map_err(|_| Status::BadRequest)?;
+
+    Ok(format.reply(&config.prepare(&grant.0, &request)?, PrepareResponse::to_bytes))
+}
+
+#[post("/acl/presign", data = "<body>")]
+fn presign(config: State<IssuerConfig>, format: Format, body: Data) -> Result<Content<Vec<u8>>, Status> {
+    let request = format.decode(&read(body)?, PresignRequest::from_bytes)?;
+
+    Ok(format.reply(&config.presign(&request)?, PresignResponse::to_bytes))
+}
+
+pub fn routes() -> Vec<Route> {
+    routes![prepare, presign]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::attributes::Opening;
+    use crate::token::Token;
+    use crate::user::UserParameters;
+    use crate::verifying::VerifyingKey;
+    use rocket::http::{Accept, Header};
+    use rocket::local::Client;
+
+    fn schema() -> Schema {
+        Schema::new(&["user id", "name", "expiry"])
+    }
+
+    // alice may receive her name, and the issuer sets the expiry
+    fn authenticate(request: &Request) -> Option<Vec<(usize, Scalar)>> {
+        match request.headers().get_one("Authorization") {
+            Some("alice") => Some(vec![(1, Scalar::from(7u8)), (2, Scalar::from(2030u16))]),
+            _ => None,
+        }
+    }
+
+    fn client(signing_key: SigningKey) -> Client {
+        let config = IssuerConfig::new(signing_key, schema(), authenticate).with_session_limits(1, Duration::from_secs(60));
+        Client::new(rocket::ignite().manage(config).mount("/", routes())).unwrap()
+    }
+
+    fn issuance_request(name: u8) -> (Opening, IssuanceRequest) {
+        let opening = Opening::new(&mut OsRng, vec![Scalar::from(1234u16), Scalar::from(name), Scalar::ZERO]);
+        let request = IssuanceRequest::with_issuer_attributes(&mut OsRng, &schema(), &opening, &[1], &[2]).unwrap();
+        (opening, request)
+    }
+
+    #[test]
+    fn issues_granted_attributes_over_http() {
+        let signing_key = SigningKey::from_bytes(&[7u8; 32]);
+        let user_params = UserParameters {
+            key: VerifyingKey::from(&signing_key),
+        };
+        let client = client(signing_key);
+        let (mut opening, request) = issuance_request(7);
+
+        let prepare = |request: &IssuanceRequest, login: &'static str| {
+            client
+                .post("/acl/prepare")
+                .header(Header::new("Authorization", login))
+                .header(ContentType::JSON)
+                .body(serde_json::to_vec(request).unwrap())
+                .dispatch()
+        };
+
+        assert_eq!(prepare(&request, "mallory").status(), Status::Unauthorized);
+        assert_eq!(prepare(&issuance_request(8).1, "alice").status(), Status::Forbidden);
+
+        let mut response = prepare(&request, "alice");
+        assert_eq!(response.status(), Status::Ok);
+        let prepared: PrepareResponse = serde_json::from_slice(&response.body_bytes().unwrap()).unwrap();
+        assert_eq!(prepared.issuer_attributes, vec![(2, Scalar::from(2030u16))]);
+
+        // the only session is taken until it is answered
+        assert_eq!(prepare(&request, "alice").status(), Status::ServiceUnavailable);
+
+        opening.attributes[2] = prepared.issuer_attributes[0].1;
+        let commitment = opening.commit(&schema());
+        let (user_state, challenge) = user_params
+            .compute_challenge(&mut OsRng, &commitment, &[0u8; 64], &prepared.message)
+            .unwrap();
+
+        let presign = PresignRequest {
+            session: prepared.session,
+            challenge,
+        };
+        let presign_binary = || {
+            client
+                .post("/acl/presign")
+                .header(ContentType::Binary)
+                .header(Accept::Binary)
+                .body(presign.to_bytes())
+                .dispatch()
+        };
+
+        let mut response = presign_binary();
+        assert_eq!(response.status(), Status::Ok);
+        let presignature = response.body_bytes().unwrap();
+        let token = Token::new(user_params.compute_signature(&user_state, &presignature).unwrap(), opening);
+        assert_eq!(token.opening.commit(&schema()), commitment);
+
+        // a session answers exactly one challenge
+        assert_eq!(presign_binary().status(), Status::NotFound);
+    }
+}
@@ -2,12 +2,19 @@ use std::array::TryFromSliceError;
 use std::error::Error;
 use std::fmt;
 use std::fmt::Display;
+use std::io;
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
 pub enum SigningError {
     CompressedPointFormat,
     PointDecompression,
     ScalarFormat,
+    AttributeIndex,
+    InvalidProof,
+    BatchSize,
+    Credential { err: VerifyingError },
+    Threshold,
+    InvalidShare { index: u32 },
 }
 
 impl Display for SigningError {
@@ -18,6 +25,12 @@ impl Display for SigningError {
             }
             SigningError::PointDecompression => write!(f, "Cannot decompress Ristretto point"),
             SigningError::ScalarFormat => write!(f, "Scalar is not canonically formatted"),
+            SigningError::AttributeIndex => write!(f, "Attribute index is out of range or out of order"),
+            SigningError::InvalidProof => write!(f, "Proof of the commitment opening is invalid"),
+            SigningError::BatchSize => write!(f, "Batch is empty, too large, or framed incorrectly"),
+            SigningError::Threshold => write!(f, "Signing set or session does not fit the threshold sharing"),
+            SigningError::InvalidShare { index } => write!(f, "Signing node {} sent an invalid response", index),
+            SigningError::Credential { err } => write!(f, "Credential to refresh is invalid: {}", err),
         }
     }
 }
@@ -37,6 +50,15 @@ pub enum VerifyingError {
     Invalid,
     ScalarFormat,
     KeyFormat,
+    AttributeIndex,
+    InvalidProof,
+    Replayed,
+    RateLimited,
+    Revoked,
+    UnexpectedKey,
+    DuplicateIssuer,
+    StaleDirectory,
+    Store { err: StoreError },
 }
 
 impl Display for VerifyingError {
@@ -49,18 +71,174 @@ impl Display for VerifyingError {
             VerifyingError::Invalid => write!(f, "Signature is invalid"),
             VerifyingError::ScalarFormat => write!(f, "Scalar is not canonically formatted"),
             VerifyingError::KeyFormat => write!(f, "Verifying key is incorrectly formatted or cannot be decompressed"),
+            VerifyingError::AttributeIndex => write!(f, "Attribute index is out of range, out of order or not hidden"),
+            VerifyingError::InvalidProof => write!(f, "Presentation proof is invalid"),
+            VerifyingError::Replayed => write!(f, "Signature has already been spent"),
+            VerifyingError::Revoked => write!(f, "Credential has been revoked"),
+            VerifyingError::UnexpectedKey => write!(f, "Key is not the one listed in the key directory"),
+            VerifyingError::DuplicateIssuer => write!(f, "Issuer is listed more than once in the key directory"),
+            VerifyingError::StaleDirectory => write!(f, "Key directory is older than the last one accepted"),
+            VerifyingError::RateLimited => write!(f, "Secret has been shown too often in this epoch"),
+            VerifyingError::Store { err } => write!(f, "Cannot record spent signature: {}", err),
         }
     }
 }
 
 impl Error for VerifyingError {}
 
+impl From<StoreError> for VerifyingError {
+    fn from(err: StoreError) -> VerifyingError {
+        VerifyingError::Store { err }
+    }
+}
+
 impl From<TryFromSliceError> for VerifyingError {
     fn from(_: TryFromSliceError) -> VerifyingError {
         VerifyingError::CompressedPointFormat
     }
 }
 
+// CredentialError is why a request carried no acceptable presentation, see
+// the web module
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum CredentialError {
+    Unconfigured,
+    Missing,
+    Format,
+    Invalid { err: VerifyingError },
+}
+
+impl Display for CredentialError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            CredentialError::Unconfigured => write!(f, "No credential configuration is managed for this schema"),
+            CredentialError::Missing => write!(f, "Request carries no presentation"),
+            CredentialError::Format => write!(f, "Presentation is incorrectly encoded"),
+            CredentialError::Invalid { err } => write!(f, "Presentation is not acceptable: {}", err),
+        }
+    }
+}
+
+impl Error for CredentialError {}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum JwtError {
+    Invalid { err: VerifyingError },
+    Signing,
+}
+
+impl Display for JwtError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            JwtError::Invalid { err } => write!(f, "Presentation is not acceptable: {}", err),
+            JwtError::Signing => write!(f, "Cannot sign JWT with the configured key and algorithm"),
+        }
+    }
+}
+
+impl Error for JwtError {}
+
+// ClientError is why an IssuanceClient could not obtain a credential
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum ClientError {
+    Transport,
+    Status { code: u16 },
+    Format,
+    User { err: UserError },
+}
+
+impl Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            ClientError::Transport => write!(f, "Cannot reach the issuer"),
+            ClientError::Status { code } => write!(f, "Issuer answered with status {}", code),
+            ClientError::Format => write!(f, "Issuer response is incorrectly formatted"),
+            ClientError::User { err } => write!(f, "Cannot complete issuance: {}", err),
+        }
+    }
+}
+
+impl Error for ClientError {}
+
+impl From<UserError> for ClientError {
+    fn from(err: UserError) -> ClientError {
+        ClientError::User { err }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum WalletError {
+    Io { kind: io::ErrorKind },
+    Exists,
+    Format,
+    Iterations,
+    Passphrase,
+    Position,
+}
+
+impl Display for WalletError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            WalletError::Io { kind } => write!(f, "Wallet I/O error: {}", kind),
+            WalletError::Exists => write!(f, "Wallet file already exists"),
+            WalletError::Format => write!(f, "Wallet is incorrectly formatted"),
+            WalletError::Iterations => write!(f, "PBKDF2 iteration count is out of bounds"),
+            WalletError::Passphrase => write!(f, "Wrong passphrase, or the wallet has been tampered with"),
+            WalletError::Position => write!(f, "No wallet entry at this position"),
+        }
+    }
+}
+
+impl Error for WalletError {}
+
+impl From<io::Error> for WalletError {
+    fn from(err: io::Error) -> WalletError {
+        WalletError::Io { kind: err.kind() }
+    }
+}
+
+impl From<TryFromSliceError> for WalletError {
+    fn from(_: TryFromSliceError) -> WalletError {
+        WalletError::Format
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum StoreError {
+    Io { kind: io::ErrorKind },
+    Poisoned,
+}
+
+impl Display for StoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            StoreError::Io { kind } => write!(f, "Spent token store I/O error: {}", kind),
+            StoreError::Poisoned => write!(f, "Spent token store lock is poisoned"),
+        }
+    }
+}
+
+impl Error for StoreError {}
+
+impl From<io::Error> for StoreError {
+    fn from(err: io::Error) -> StoreError {
+        StoreError::Io { kind: err.kind() }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct BatchVerifyingError {
+    pub failed: Vec<usize>,
+}
+
+impl Display for BatchVerifyingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Signatures at indices {:?} are invalid", self.failed)
+    }
+}
+
+impl Error for BatchVerifyingError {}
+
 #[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
 pub enum UserError {
     CompressedPointFormat,
@@ -68,6 +246,12 @@ pub enum UserError {
     RndZero,
     ScalarFormat,
     GammaZero,
+    AttributeIndex,
+    OpeningLength,
+    BatchSize,
+    ShowIndex,
+    Revoked,
+    Expired,
     Invalid { err: VerifyingError },
 }
 
@@ -89,6 +273,12 @@ impl Display for UserError {
             UserError::RndZero => write!(f, "Signer did not generate a non-zero value for rnd"),
             UserError::ScalarFormat => write!(f, "Scalar is not canonically formatted"),
             UserError::GammaZero => write!(f, "Accidentally generated a zero value for gamma"),
+            UserError::AttributeIndex => write!(f, "Attribute index is out of range or out of order"),
+            UserError::OpeningLength => write!(f, "Opening does not have one value per schema attribute"),
+            UserError::BatchSize => write!(f, "Batch is empty, too large, or framed incorrectly"),
+            UserError::Revoked => write!(f, "Credential has been revoked"),
+            UserError::Expired => write!(f, "Credential's expiry is outside the issuer's window"),
+            UserError::ShowIndex => write!(f, "Show index is missing, unexpected, or not below the rate limit"),
             UserError::Invalid { err } => write!(f, "Invalid signature: {}", err),
         }
     }
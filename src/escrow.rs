@@ -0,0 +1,60 @@
+use curve25519_dalek::ristretto::RistrettoPoint;
+use curve25519_dalek::scalar::Scalar;
+
+use rand_core::{CryptoRng, RngCore};
+
+use serde::{Deserialize, Serialize};
+
+// TrusteeKey is the secret key of a trustee that can de-anonymize
+// presentations made under a policy with escrow. Presentations encrypt the
+// user id attribute m in the exponent, as (rho*G, m*G + rho*T), so opening
+// yields m*G rather than m; the trustee (or the issuer, who knows which user
+// got which id) matches it against the ids on record with identifies.
+#[derive(Clone)]
+pub struct TrusteeKey {
+    pub(crate) scalar: Scalar,
+}
+
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct TrusteePublicKey {
+    pub(crate) point: RistrettoPoint,
+}
+
+// Ciphertext is an ElGamal encryption of a user id to a TrusteePublicKey
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Ciphertext {
+    pub c1: RistrettoPoint,
+    pub c2: RistrettoPoint,
+}
+
+impl TrusteeKey {
+    pub fn random<R: RngCore + CryptoRng>(rng: &mut R) -> TrusteeKey {
+        TrusteeKey {
+            scalar: Scalar::random(rng),
+        }
+    }
+
+    pub fn public_key(&self) -> TrusteePublicKey {
+        TrusteePublicKey {
+            point: RistrettoPoint::mul_base(&self.scalar),
+        }
+    }
+
+    // open decrypts a ciphertext to m*G for the encrypted user id m
+    pub fn open(&self, ciphertext: &Ciphertext) -> RistrettoPoint {
+        ciphertext.c2 - ciphertext.c1 * self.scalar
+    }
+
+    pub fn identifies(&self, ciphertext: &Ciphertext, user_id: &Scalar) -> bool {
+        self.open(ciphertext) == RistrettoPoint::mul_base(user_id)
+    }
+}
+
+impl TrusteePublicKey {
+    pub(crate) fn encrypt(&self, user_id: &Scalar, randomness: &Scalar) -> Ciphertext {
+        Ciphertext {
+            c1: RistrettoPoint::mul_base(randomness),
+            c2: RistrettoPoint::mul_base(user_id) + self.point * randomness,
+        }
+    }
+}
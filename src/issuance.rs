@@ -0,0 +1,638 @@
+use crate::attributes::{Opening, Schema};
+use crate::constants::gen_h;
+use crate::errors::{SigningError, UserError};
+use crate::presentation::{delta_tag, relate, transcript};
+use crate::proof::{Proof, Statement, Transcript};
+use crate::signature::Signature;
+use crate::token::Token;
+use crate::verifying::VerifyingKey;
+
+use curve25519_dalek::constants::RISTRETTO_BASEPOINT_POINT;
+use curve25519_dalek::ristretto::RistrettoPoint;
+use curve25519_dalek::scalar::Scalar;
+use curve25519_dalek::traits::{Identity, VartimeMultiscalarMul};
+
+use rand_core::{CryptoRng, RngCore};
+
+use serde::{Deserialize, Serialize};
+
+// IssuanceRequest is what the user sends to the issuer instead of a bare
+// commitment: the commitment itself, the attributes the user chooses to reveal
+// to the issuer, the attributes left for the issuer to assign, and a proof that
+// the commitment opens under the schema to the revealed values plus hidden
+// ones (and nothing at the issuer's indices). Revealed attributes are listed as
+// (schema index, value), and all indices are in increasing order.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct IssuanceRequest {
+    pub commitment: RistrettoPoint,
+    pub revealed: Vec<(usize, Scalar)>,
+    pub issuer_assigned: Vec<usize>,
+    pub(crate) proof: Proof,
+}
+
+impl IssuanceRequest {
+    pub fn new<R: RngCore + CryptoRng>(
+        rng: &mut R,
+        schema: &Schema,
+        opening: &Opening,
+        revealed: &[usize],
+    ) -> Result<IssuanceRequest, UserError> {
+        IssuanceRequest::with_issuer_attributes(rng, schema, opening, revealed, &[])
+    }
+
+    // with_issuer_attributes leaves the attributes at issuer_assigned out of the
+    // commitment so that the issuer can add its own values before signing. The
+    // opening's values at those indices are ignored; once the issuer has sent
+    // its values, the user stores them in the opening.
+    pub fn with_issuer_attributes<R: RngCore + CryptoRng>(
+        rng: &mut R,
+        schema: &Schema,
+        opening: &Opening,
+        revealed: &[usize],
+        issuer_assigned: &[usize],
+    ) -> Result<IssuanceRequest, UserError> {
+        if opening.attributes.len() != schema.len() {
+            return Err(UserError::OpeningLength);
+        }
+
+        let mut opening = opening.clone();
+        for index in issuer_assigned {
+            *opening.attributes.get_mut(*index).ok_or(UserError::AttributeIndex)? = Scalar::ZERO;
+        }
+
+        let revealed: Vec<(usize, Scalar)> = revealed
+            .iter()
+            .map(|index| Ok((*index, *opening.attributes.get(*index).ok_or(UserError::AttributeIndex)?)))
+            .collect::<Result<_, UserError>>()?;
+        let commitment = opening.commit(schema);
+
+        let statement = statement(schema, &commitment, &revealed, issuer_assigned, Some(&opening))
+            .ok_or(UserError::AttributeIndex)?;
+
+        Ok(IssuanceRequest {
+            commitment,
+            proof: statement.prove(rng, Transcript::new(b"acl issuance request")),
+            revealed,
+            issuer_assigned: issuer_assigned.to_vec(),
+        })
+    }
+
+    pub fn verify(&self, schema: &Schema) -> Result<(), SigningError> {
+        let statement = statement(schema, &self.commitment, &self.revealed, &self.issuer_assigned, None)
+            .ok_or(SigningError::AttributeIndex)?;
+
+        if statement.verify(Transcript::new(b"acl issuance request"), &self.proof) {
+            Ok(())
+        } else {
+            Err(SigningError::InvalidProof)
+        }
+    }
+
+    // certified_commitment adds the issuer's values, given as (schema index,
+    // value) for exactly the indices in issuer_assigned, to the user's
+    // commitment. The result is the commitment the issuer signs, and equals
+    // Opening::commit once the user has stored the same values.
+    pub fn certified_commitment(
+        &self,
+        schema: &Schema,
+        issuer_attributes: &[(usize, Scalar)],
+    ) -> Result<RistrettoPoint, SigningError> {
+        certify(schema, &self.commitment, &self.issuer_assigned, issuer_attributes)
+    }
+}
+
+// ReissuanceRequest asks the issuer to refresh a credential: the new
+// commitment keeps every hidden attribute of the old token except those at
+// refreshed (e.g. an expiry date), which are left for the issuer to assign as
+// in IssuanceRequest::with_issuer_attributes. The proof shows that the old
+// signature certifies the same attributes, without revealing any of them, and
+// that the old expiry is within the issuer's ExpiryWindow.
+//
+// The issuer learns the old signature, and SigningKey::prepare_reissued
+// records it in a SpentTokenStore to refresh each credential only once.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct ReissuanceRequest {
+    pub commitment: RistrettoPoint,
+    pub refreshed: Vec<usize>,
+    pub signature: Signature,
+    pub blinded_commitment: RistrettoPoint,
+    pub hashed_message: Vec<u8>,
+    pub expiry: Option<RistrettoPoint>,
+    pub(crate) delta_tag: RistrettoPoint,
+    pub(crate) proof: Proof,
+}
+
+// ExpiryWindow is the issuer's condition on the old credential's hidden
+// attribute at index, read as an integer (e.g. a day number): it must be in
+// not_before..not_before + window, so not expired as of not_before and at
+// most window - 1 ahead of it. The request commits to it as expiry*G + t*H
+// and proves in zero knowledge which value in the window it is. Proofs grow
+// linearly with window.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct ExpiryWindow {
+    pub index: usize,
+    pub not_before: u64,
+    pub window: u32,
+}
+
+// ReissuanceWitness is what only the user knows about a ReissuanceRequest
+struct ReissuanceWitness<'a> {
+    token: &'a Token,
+    opening: &'a Opening,
+    offset: u32,
+    expiry_blinding: Scalar,
+}
+
+impl ReissuanceRequest {
+    // new returns the request along with the opening of its commitment, which
+    // the user completes with the issuer's values once they arrive. expiry is
+    // the issuer's window, if it has one.
+    pub fn new<R: RngCore + CryptoRng>(
+        rng: &mut R,
+        token: &Token,
+        key: &VerifyingKey,
+        schema: &Schema,
+        refreshed: &[usize],
+        expiry: Option<&ExpiryWindow>,
+    ) -> Result<(ReissuanceRequest, Opening), UserError> {
+        if token.opening.attributes.len() != schema.len() {
+            return Err(UserError::OpeningLength);
+        }
+
+        let expiry_blinding = Scalar::random(rng);
+        let (offset, expiry_commitment) = match expiry {
+            Some(expiry) => {
+                let value = *token.opening.attributes.get(expiry.index).ok_or(UserError::AttributeIndex)?;
+                let offset = expiry_offset(&value, expiry).ok_or(UserError::Expired)?;
+                (offset, Some(RistrettoPoint::mul_base(&value) + gen_h() * expiry_blinding))
+            }
+            None => (0, None),
+        };
+
+        let mut attributes = token.opening.attributes.clone();
+        for index in refreshed {
+            *attributes.get_mut(*index).ok_or(UserError::AttributeIndex)? = Scalar::ZERO;
+        }
+        let opening = Opening::new(rng, attributes);
+
+        let mut request = ReissuanceRequest {
+            commitment: opening.commit(schema),
+            refreshed: refreshed.to_vec(),
+            signature: token.signature,
+            blinded_commitment: token.blinded_commitment,
+            hashed_message: token.hashed_message.clone(),
+            expiry: expiry_commitment,
+            delta_tag: delta_tag(token),
+            proof: Proof::default(),
+        };
+
+        let witness = ReissuanceWitness {
+            token,
+            opening: &opening,
+            offset,
+            expiry_blinding,
+        };
+        let (statement, alternatives) = request
+            .statement(schema, expiry, Some(&witness))
+            .ok_or(UserError::AttributeIndex)?;
+        request.proof = statement.prove_or(&alternatives, offset as usize, rng, request.transcript(key));
+
+        Ok((request, opening))
+    }
+
+    // verify checks the request against the issuer's expiry window, which
+    // must be the one the user built it for
+    pub fn verify(&self, key: &VerifyingKey, schema: &Schema, expiry: Option<&ExpiryWindow>) -> Result<(), SigningError> {
+        key.verify_prehashed(&self.hashed_message, &self.blinded_commitment, &self.signature)
+            .map_err(|err| SigningError::Credential { err })?;
+
+        if self.delta_tag == RistrettoPoint::identity()
+            || self.expiry.is_some() != expiry.is_some()
+            || expiry.is_some_and(|expiry| expiry.window == 0)
+        {
+            return Err(SigningError::InvalidProof);
+        }
+
+        let (statement, alternatives) = self.statement(schema, expiry, None).ok_or(SigningError::AttributeIndex)?;
+
+        if statement.verify_or(&alternatives, self.transcript(key), &self.proof) {
+            Ok(())
+        } else {
+            Err(SigningError::InvalidProof)
+        }
+    }
+
+    // certified_commitment is IssuanceRequest::certified_commitment, with the
+    // issuer's values for exactly the refreshed indices
+    pub fn certified_commitment(
+        &self,
+        schema: &Schema,
+        issuer_attributes: &[(usize, Scalar)],
+    ) -> Result<RistrettoPoint, SigningError> {
+        certify(schema, &self.commitment, &self.refreshed, issuer_attributes)
+    }
+
+    // statement is the old credential's relation (all attributes hidden) plus
+    // commitment = r'*H + sum(m_j * G_j) over the kept attributes, sharing the
+    // variables of the old relation. With an expiry window, it also opens the
+    // expiry commitment to the old expiry, and adds one alternative per value
+    // in the window, of which the witness fills in the one at its offset.
+    fn statement(
+        &self,
+        schema: &Schema,
+        expiry: Option<&ExpiryWindow>,
+        witness: Option<&ReissuanceWitness>,
+    ) -> Option<(Statement, Vec<Statement>)> {
+        if self.refreshed.windows(2).any(|pair| pair[0] >= pair[1])
+            || self.refreshed.iter().any(|index| *index >= schema.len())
+        {
+            return None;
+        }
+
+        let mut statement = Statement::default();
+        let vars = relate(
+            &mut statement,
+            schema,
+            &self.blinded_commitment,
+            &self.delta_tag,
+            &[],
+            witness.map(|w| w.token),
+        )?;
+
+        let blinding = statement.allocate(witness.map(|w| w.opening.blinding).unwrap_or(Scalar::ZERO));
+        let mut terms = vec![(blinding, *gen_h())];
+        for (index, generator) in schema.generators.iter().enumerate() {
+            if !self.refreshed.contains(&index) {
+                terms.push(((vars.attributes[index])?, *generator));
+            }
+        }
+        statement.constrain(self.commitment, terms);
+
+        let mut alternatives = Vec::new();
+        if let (Some(expiry), Some(commitment)) = (expiry, &self.expiry) {
+            let old_expiry = (*vars.attributes.get(expiry.index)?)?;
+            let expiry_blinding = statement.allocate(witness.map(|w| w.expiry_blinding).unwrap_or(Scalar::ZERO));
+            statement.constrain(
+                *commitment,
+                vec![(old_expiry, RISTRETTO_BASEPOINT_POINT), (expiry_blinding, *gen_h())],
+            );
+
+            for offset in 0..expiry.window {
+                let real = witness.filter(|w| w.offset == offset);
+
+                let mut alternative = Statement::default();
+                let expiry_blinding = alternative.allocate(real.map(|w| w.expiry_blinding).unwrap_or(Scalar::ZERO));
+                let value = Scalar::from(expiry.not_before) + Scalar::from(offset);
+                alternative.constrain(commitment - RistrettoPoint::mul_base(&value), vec![(expiry_blinding, *gen_h())]);
+                alternatives.push(alternative);
+            }
+        }
+
+        Some((statement, alternatives))
+    }
+
+    fn transcript(&self, key: &VerifyingKey) -> Transcript {
+        transcript(b"acl reissuance request", key, &self.signature, &self.hashed_message, &[])
+    }
+}
+
+// expiry_offset is how far into the window value is, if it's in the window
+fn expiry_offset(value: &Scalar, expiry: &ExpiryWindow) -> Option<u32> {
+    let bytes = value.as_bytes();
+    if bytes[8..].iter().any(|byte| *byte != 0) {
+        return None;
+    }
+
+    let offset = u64::from_le_bytes(bytes[..8].try_into().expect("8 bytes")).checked_sub(expiry.not_before)?;
+    u32::try_from(offset).ok().filter(|offset| *offset < expiry.window)
+}
+
+// certify adds the issuer's values, given as (schema index, value) for exactly
+// the indices in assigned, to a user's commitment
+fn certify(
+    schema: &Schema,
+    commitment: &RistrettoPoint,
+    assigned: &[usize],
+    issuer_attributes: &[(usize, Scalar)],
+) -> Result<RistrettoPoint, SigningError> {
+    if issuer_attributes.len() != assigned.len()
+        || issuer_attributes
+            .iter()
+            .zip(assigned.iter())
+            .any(|((index, _), assigned)| index != assigned || *index >= schema.len())
+    {
+        return Err(SigningError::AttributeIndex);
+    }
+
+    Ok(RistrettoPoint::vartime_multiscalar_mul(
+        [Scalar::ONE].into_iter().chain(issuer_attributes.iter().map(|(_, value)| *value)),
+        [commitment]
+            .into_iter()
+            .chain(issuer_attributes.iter().map(|(index, _)| &schema.generators[*index])),
+    ))
+}
+
+// statement builds commitment - sum(revealed m_i * G_i) = r*H + sum(hidden m_j * G_j),
+// where hidden attributes are those neither revealed nor assigned by the
+// issuer. It returns None if the indices are out of range, not strictly
+// increasing, or both revealed and assigned by the issuer.
+fn statement(
+    schema: &Schema,
+    commitment: &RistrettoPoint,
+    revealed: &[(usize, Scalar)],
+    issuer_assigned: &[usize],
+    opening: Option<&Opening>,
+) -> Option<Statement> {
+    let increasing = |indices: &mut dyn Iterator<Item = usize>| {
+        let indices: Vec<usize> = indices.collect();
+        indices.windows(2).all(|pair| pair[0] < pair[1]) && indices.iter().all(|index| *index < schema.len())
+    };
+
+    if !increasing(&mut revealed.iter().map(|(index, _)| *index))
+        || !increasing(&mut issuer_assigned.iter().copied())
+        || revealed.iter().any(|(index, _)| issuer_assigned.contains(index))
+    {
+        return None;
+    }
+
+    let witness = |value: Option<Scalar>| value.unwrap_or(Scalar::ZERO);
+
+    let mut statement = Statement::default();
+    let mut terms = vec![(statement.allocate(witness(opening.map(|o| o.blinding))), *gen_h())];
+    let mut lhs = *commitment;
+
+    for (index, generator) in schema.generators.iter().enumerate() {
+        if issuer_assigned.contains(&index) {
+            continue;
+        }
+
+        match revealed.iter().find(|(revealed_index, _)| *revealed_index == index) {
+            Some((_, value)) => lhs -= generator * value,
+            None => terms.push((
+                statement.allocate(witness(opening.map(|o| o.attributes[index]))),
+                *generator,
+            )),
+        }
+    }
+
+    statement.constrain(lhs, terms);
+    Some(statement)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::errors::VerifyingError;
+    use crate::signing::SigningKey;
+    use crate::spent::MemorySpentTokenStore;
+    use crate::token::tests::issue;
+    use crate::user::UserParameters;
+    use crate::verifying::VerifyingKey;
+    use rand_core::OsRng;
+
+    fn schema() -> Schema {
+        Schema::new(&["user id", "type", "sports", "tech"])
+    }
+
+    fn window() -> ExpiryWindow {
+        ExpiryWindow {
+            index: 1,
+            not_before: 2024,
+            window: 2,
+        }
+    }
+
+    fn opening() -> Opening {
+        Opening::new(
+            &mut OsRng,
+            vec![Scalar::from(1u8), Scalar::from(2u8), Scalar::ONE, Scalar::ZERO],
+        )
+    }
+
+    #[test]
+    fn prepare_verified_accepts_valid_requests() {
+        let signing_key = SigningKey::from_bytes(&[7u8; 32]);
+        let request = IssuanceRequest::new(&mut OsRng, &schema(), &opening(), &[1, 2]).unwrap();
+
+        assert_eq!(request.revealed, vec![(1, Scalar::from(2u8)), (2, Scalar::ONE)]);
+        assert!(signing_key.prepare_verified(&schema(), &request).is_ok());
+    }
+
+    #[test]
+    fn prepare_verified_refuses_invalid_requests() {
+        let signing_key = SigningKey::from_bytes(&[7u8; 32]);
+        let request = IssuanceRequest::new(&mut OsRng, &schema(), &opening(), &[1]).unwrap();
+
+        let mut lied = request.clone();
+        lied.revealed[0].1 = Scalar::from(64u8);
+        assert_eq!(signing_key.prepare_verified(&schema(), &lied).unwrap_err(), SigningError::InvalidProof);
+
+        let mut swapped = request.clone();
+        swapped.commitment = opening().commit(&schema());
+        assert_eq!(signing_key.prepare_verified(&schema(), &swapped).unwrap_err(), SigningError::InvalidProof);
+
+        let other_schema = Schema::new(&["user id", "type", "sports", "news"]);
+        assert_eq!(signing_key.prepare_verified(&other_schema, &request).unwrap_err(), SigningError::InvalidProof);
+
+        let mut out_of_range = request;
+        out_of_range.revealed[0].0 = 4;
+        assert_eq!(signing_key.prepare_verified(&schema(), &out_of_range).unwrap_err(), SigningError::AttributeIndex);
+    }
+
+    #[test]
+    fn issuer_assigned_attributes_are_certified() {
+        let signing_key = SigningKey::from_bytes(&[7u8; 32]);
+        let user_params = UserParameters {
+            key: VerifyingKey::from(&signing_key),
+        };
+
+        // the user tries to sneak in their own type; it must not end up in the
+        // certified commitment
+        let mut opening = opening();
+        let request = IssuanceRequest::with_issuer_attributes(&mut OsRng, &schema(), &opening, &[], &[1, 2, 3]).unwrap();
+
+        let issuer_attributes = [(1, Scalar::from(64u8)), (2, Scalar::ZERO), (3, Scalar::ONE)];
+        assert_eq!(
+            signing_key.prepare_with_attributes(&schema(), &request, &issuer_attributes[..2]).unwrap_err(),
+            SigningError::AttributeIndex
+        );
+        assert_eq!(
+            signing_key.prepare_verified(&schema(), &request).unwrap_err(),
+            SigningError::AttributeIndex
+        );
+
+        let (ss, prepare_message) = signing_key
+            .prepare_with_attributes(&schema(), &request, &issuer_attributes)
+            .unwrap();
+
+        for (index, value) in issuer_attributes {
+            opening.attributes[index] = value;
+        }
+        let commitment = opening.commit(&schema());
+        assert_eq!(request.certified_commitment(&schema(), &issuer_attributes), Ok(commitment));
+
+        let (us, challenge) = user_params
+            .compute_challenge(&mut OsRng, &commitment, &[0u8; 64], &prepare_message)
+            .unwrap();
+        let presignature = signing_key.compute_presignature(ss, &challenge).unwrap();
+        assert!(user_params.compute_signature(&us, &presignature).is_ok());
+    }
+
+    #[test]
+    fn batch_issuance_checks_every_request() {
+        let signing_key = SigningKey::from_bytes(&[7u8; 32]);
+        let user_params = UserParameters {
+            key: VerifyingKey::from(&signing_key),
+        };
+
+        let openings = [opening(), opening()];
+        let requests: Vec<IssuanceRequest> = openings
+            .iter()
+            .map(|opening| IssuanceRequest::with_issuer_attributes(&mut OsRng, &schema(), opening, &[], &[1]).unwrap())
+            .collect();
+        let issuer_attributes: [&[(usize, Scalar)]; 2] = [&[(1, Scalar::from(3u8))], &[(1, Scalar::from(4u8))]];
+
+        let mut lied = requests.clone();
+        lied[1].commitment = opening().commit(&schema());
+        assert_eq!(
+            signing_key.prepare_batch_with_attributes(&schema(), &lied, &issuer_attributes).unwrap_err(),
+            SigningError::InvalidProof
+        );
+        assert_eq!(
+            signing_key.prepare_batch_with_attributes(&schema(), &requests, &[&[][..]; 2]).unwrap_err(),
+            SigningError::AttributeIndex
+        );
+        assert_eq!(
+            signing_key.prepare_batch_with_attributes(&schema(), &requests, &issuer_attributes[..1]).unwrap_err(),
+            SigningError::BatchSize
+        );
+
+        let (ss, prepare_message) = signing_key
+            .prepare_batch_with_attributes(&schema(), &requests, &issuer_attributes)
+            .unwrap();
+        let commitments: Vec<RistrettoPoint> = openings
+            .into_iter()
+            .zip(issuer_attributes)
+            .map(|(mut opening, issuer_attributes)| {
+                opening.attributes[1] = issuer_attributes[0].1;
+                opening.commit(&schema())
+            })
+            .collect();
+        let batch: Vec<(&RistrettoPoint, &[u8])> = commitments.iter().map(|c| (c, &[0u8; 64][..])).collect();
+        let (us, challenges) = user_params
+            .compute_challenge_batch(&mut OsRng, &batch, &prepare_message)
+            .unwrap();
+        let presignatures = signing_key.compute_presignature_batch(ss, &challenges).unwrap();
+
+        for credential in user_params.compute_signature_batch(&us, &presignatures).unwrap() {
+            assert_eq!(credential.verify(&user_params.key), Ok(()));
+        }
+    }
+
+    #[test]
+    fn issuer_assigned_attributes_cannot_overlap_the_users() {
+        let signing_key = SigningKey::from_bytes(&[7u8; 32]);
+
+        assert_eq!(
+            IssuanceRequest::with_issuer_attributes(&mut OsRng, &schema(), &opening(), &[1], &[1]).unwrap_err(),
+            UserError::AttributeIndex
+        );
+
+        // a request whose hidden attributes include an issuer index fails the
+        // proof once the issuer index is declared
+        let mut request = IssuanceRequest::new(&mut OsRng, &schema(), &opening(), &[]).unwrap();
+        request.issuer_assigned = vec![1];
+        assert_eq!(
+            signing_key
+                .prepare_with_attributes(&schema(), &request, &[(1, Scalar::from(64u8))])
+                .unwrap_err(),
+            SigningError::InvalidProof
+        );
+    }
+
+    #[test]
+    fn reissuance_keeps_hidden_attributes() {
+        let signing_key = SigningKey::from_bytes(&[7u8; 32]);
+        let key = VerifyingKey::from(&signing_key);
+        let user_params = UserParameters { key };
+
+        // attribute 1 stands in for the expiry
+        let old = issue(&signing_key, &schema(), vec![Scalar::from(99u8), Scalar::from(2024u16), Scalar::ONE, Scalar::ZERO]);
+        let (request, mut opening) =
+            ReissuanceRequest::new(&mut OsRng, &old, &key, &schema(), &[1], Some(&window())).unwrap();
+        assert_eq!(request.verify(&key, &schema(), Some(&window())), Ok(()));
+
+        let spent = MemorySpentTokenStore::default();
+        let issuer_attributes = [(1, Scalar::from(2025u16))];
+        let (ss, prepare_message) = signing_key
+            .prepare_reissued(&schema(), &request, Some(&window()), &issuer_attributes, &spent)
+            .unwrap();
+
+        // each credential is refreshed once
+        assert_eq!(
+            signing_key
+                .prepare_reissued(&schema(), &request, Some(&window()), &issuer_attributes, &spent)
+                .unwrap_err(),
+            SigningError::Credential { err: VerifyingError::Replayed }
+        );
+
+        opening.attributes[1] = Scalar::from(2025u16);
+        let commitment = opening.commit(&schema());
+        let (us, challenge) = user_params
+            .compute_challenge(&mut OsRng, &commitment, &[0u8; 64], &prepare_message)
+            .unwrap();
+        let presignature = signing_key.compute_presignature(ss, &challenge).unwrap();
+        let new = Token::new(user_params.compute_signature(&us, &presignature).unwrap(), opening);
+
+        assert_eq!(
+            new.opening.attributes,
+            vec![Scalar::from(99u8), Scalar::from(2025u16), Scalar::ONE, Scalar::ZERO]
+        );
+        assert_ne!(new.opening.blinding, old.opening.blinding);
+    }
+
+    #[test]
+    fn reissuance_refuses_invalid_requests() {
+        let signing_key = SigningKey::from_bytes(&[7u8; 32]);
+        let key = VerifyingKey::from(&signing_key);
+        let old = issue(&signing_key, &schema(), vec![Scalar::from(99u8), Scalar::from(2024u16), Scalar::ONE, Scalar::ZERO]);
+        let (request, _) = ReissuanceRequest::new(&mut OsRng, &old, &key, &schema(), &[1], Some(&window())).unwrap();
+
+        // the user can't change a kept attribute
+        let mut changed = request.clone();
+        changed.commitment += schema().generators[2];
+        assert_eq!(changed.verify(&key, &schema(), Some(&window())), Err(SigningError::InvalidProof));
+
+        let mut forged = request.clone();
+        forged.signature.rho += Scalar::ONE;
+        assert_eq!(
+            forged.verify(&key, &schema(), Some(&window())),
+            Err(SigningError::Credential { err: VerifyingError::Invalid })
+        );
+
+        let mut degenerate = request.clone();
+        degenerate.delta_tag = RistrettoPoint::identity();
+        assert_eq!(degenerate.verify(&key, &schema(), Some(&window())), Err(SigningError::InvalidProof));
+
+        // the proof is for the window the user was given
+        let later = ExpiryWindow {
+            not_before: 2025,
+            ..window()
+        };
+        assert_eq!(request.verify(&key, &schema(), Some(&later)), Err(SigningError::InvalidProof));
+        assert_eq!(request.verify(&key, &schema(), None), Err(SigningError::InvalidProof));
+
+        // an expired credential can't be refreshed
+        assert_eq!(
+            ReissuanceRequest::new(&mut OsRng, &old, &key, &schema(), &[1], Some(&later)).unwrap_err(),
+            UserError::Expired
+        );
+
+        let mut out_of_range = request;
+        out_of_range.refreshed = vec![4];
+        assert_eq!(
+            out_of_range.verify(&key, &schema(), Some(&window())),
+            Err(SigningError::AttributeIndex)
+        );
+    }
+}
@@ -0,0 +1,228 @@
+use crate::attributes::Schema;
+use crate::errors::{JwtError, VerifyingError};
+use crate::presentation::{Presentation, PresentationPolicy};
+use crate::spent::{SpentTokenStore, Verifier};
+
+use curve25519_dalek::scalar::Scalar;
+
+use jsonwebtoken::{encode, get_current_timestamp, Algorithm, EncodingKey, Header};
+
+use serde::{Deserialize, Serialize};
+
+use std::collections::BTreeMap;
+
+// JwtBridge lets services that only understand JWTs sit behind an ACL
+// verifier: it checks a presentation, records it as spent, and mints a
+// short-lived JWT with nothing but what the presentation disclosed. The
+// subject is the presentation's pseudonym for the policy's scope (if the
+// policy asks for one), so a service sees the same subject for the same user,
+// and services with different scopes can't link their users.
+pub struct JwtBridge<S: SpentTokenStore> {
+    verifier: Verifier<S>,
+    schema: Schema,
+    attributes: Vec<String>,
+    policy: PresentationPolicy,
+    context: Vec<u8>,
+    header: Header,
+    key: EncodingKey,
+    lifetime: u64,
+}
+
+// JwtClaims are the claims of a minted JWT. Attributes are keyed by name.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct JwtClaims {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sub: Option<String>,
+    pub iat: u64,
+    pub exp: u64,
+    pub attributes: BTreeMap<String, AttributeClaim>,
+}
+
+// AttributeClaim is an attribute value as a JSON number if it fits in a u64,
+// and as the hex encoding of the scalar's bytes otherwise
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum AttributeClaim {
+    Number(u64),
+    Scalar(String),
+}
+
+impl<S: SpentTokenStore> JwtBridge<S> {
+    // new takes the schema's attribute names in schema order, and mints JWTs
+    // valid for five minutes, see with_lifetime. It fails with
+    // JwtError::Signing if the key can't sign with the algorithm, since
+    // exchange only signs after the presentation's token is spent.
+    pub fn new<I: AsRef<str>>(
+        verifier: Verifier<S>,
+        attributes: &[I],
+        policy: PresentationPolicy,
+        context: &[u8],
+        algorithm: Algorithm,
+        key: EncodingKey,
+    ) -> Result<JwtBridge<S>, JwtError> {
+        let header = Header::new(algorithm);
+        let probe = JwtClaims {
+            sub: None,
+            iat: 0,
+            exp: 0,
+            attributes: BTreeMap::new(),
+        };
+        encode(&header, &probe, &key).map_err(|_| JwtError::Signing)?;
+
+        Ok(JwtBridge {
+            verifier,
+            schema: Schema::new(&attributes.iter().map(|name| name.as_ref()).collect::<Vec<&str>>()),
+            attributes: attributes.iter().map(|name| name.as_ref().to_string()).collect(),
+            policy,
+            context: context.to_vec(),
+            header,
+            key,
+            lifetime: 300,
+        })
+    }
+
+    // with_lifetime sets how many seconds minted JWTs are valid for
+    pub fn with_lifetime(mut self, seconds: u64) -> JwtBridge<S> {
+        self.lifetime = seconds;
+        self
+    }
+
+    pub fn schema(&self) -> &Schema {
+        &self.schema
+    }
+
+    pub fn exchange(&self, presentation: &Presentation) -> Result<String, JwtError> {
+        self.verifier
+            .verify_presentation(presentation, &self.schema, &self.policy, &self.context)
+            .map_err(|err| JwtError::Invalid { err })?;
+
+        let iat = get_current_timestamp();
+        let claims = JwtClaims {
+            sub: presentation.pseudonym.map(|nym| hex(nym.compress().as_bytes())),
+            iat,
+            exp: iat + self.lifetime,
+            attributes: presentation
+                .revealed
+                .iter()
+                .map(|(index, value)| {
+                    let name = self.attributes.get(*index).ok_or(JwtError::Invalid {
+                        err: VerifyingError::AttributeIndex,
+                    })?;
+                    Ok((name.clone(), AttributeClaim::from(value)))
+                })
+                .collect::<Result<_, JwtError>>()?,
+        };
+
+        encode(&self.header, &claims, &self.key).map_err(|_| JwtError::Signing)
+    }
+}
+
+impl From<&Scalar> for AttributeClaim {
+    fn from(value: &Scalar) -> AttributeClaim {
+        let bytes = value.as_bytes();
+        if bytes[8..].iter().all(|byte| *byte == 0) {
+            AttributeClaim::Number(u64::from_le_bytes(bytes[..8].try_into().expect("8 bytes")))
+        } else {
+            AttributeClaim::Scalar(hex(bytes))
+        }
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::signing::SigningKey;
+    use crate::spent::MemorySpentTokenStore;
+    use crate::token::tests::issue;
+    use crate::verifying::VerifyingKey;
+    use jsonwebtoken::{decode, DecodingKey, Validation};
+    use rand_core::OsRng;
+
+    const ATTRIBUTES: [&str; 4] = ["user id", "type", "sports", "tech"];
+
+    fn policy(scope: &[u8]) -> PresentationPolicy {
+        PresentationPolicy {
+            revealed: vec![1, 2],
+            pseudonym: Some((0, scope.to_vec())),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn exchanges_presentations_for_scoped_jwts() {
+        let signing_key = SigningKey::from_bytes(&[7u8; 32]);
+        let key = VerifyingKey::from(&signing_key);
+        let bridge = JwtBridge::new(
+            Verifier::new(&key, MemorySpentTokenStore::new()),
+            &ATTRIBUTES,
+            policy(b"legacy.example"),
+            b"bridge.example",
+            Algorithm::HS256,
+            EncodingKey::from_secret(b"secret"),
+        )
+        .unwrap()
+        .with_lifetime(60);
+
+        // every presentation spends its token, so each show needs a fresh one
+        let show = |scope: &[u8]| {
+            let token = issue(
+                &signing_key,
+                bridge.schema(),
+                vec![Scalar::from(1234u16), Scalar::from(2u8), Scalar::ONE, Scalar::ZERO],
+            );
+            Presentation::new(&mut OsRng, &token, &key, bridge.schema(), &policy(scope), b"bridge.example").unwrap()
+        };
+
+        let presentation = show(b"legacy.example");
+        let jwt = bridge.exchange(&presentation).unwrap();
+        let claims = decode::<JwtClaims>(&jwt, &DecodingKey::from_secret(b"secret"), &Validation::new(Algorithm::HS256))
+            .unwrap()
+            .claims;
+
+        assert_eq!(claims.exp, claims.iat + 60);
+        assert_eq!(
+            claims.attributes,
+            BTreeMap::from([
+                ("type".to_string(), AttributeClaim::Number(2)),
+                ("sports".to_string(), AttributeClaim::Number(1)),
+            ])
+        );
+
+        // the subject is stable per scope, across tokens of the same user
+        let again = bridge.exchange(&show(b"legacy.example")).unwrap();
+        let again = decode::<JwtClaims>(&again, &DecodingKey::from_secret(b"secret"), &Validation::new(Algorithm::HS256))
+            .unwrap()
+            .claims;
+        assert_eq!(again.sub, claims.sub);
+
+        assert_eq!(
+            bridge.exchange(&presentation),
+            Err(JwtError::Invalid {
+                err: VerifyingError::Replayed
+            })
+        );
+        assert!(matches!(
+            bridge.exchange(&show(b"other.example")),
+            Err(JwtError::Invalid { .. })
+        ));
+    }
+
+    #[test]
+    fn mismatched_keys_are_refused_up_front() {
+        let key = VerifyingKey::from(&SigningKey::from_bytes(&[7u8; 32]));
+        let bridge = JwtBridge::new(
+            Verifier::new(&key, MemorySpentTokenStore::new()),
+            &ATTRIBUTES,
+            policy(b"legacy.example"),
+            b"bridge.example",
+            Algorithm::RS256,
+            EncodingKey::from_secret(b"secret"),
+        );
+
+        assert_eq!(bridge.err(), Some(JwtError::Signing));
+    }
+}
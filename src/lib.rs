@@ -1,16 +1,64 @@
+#![cfg_attr(feature = "rocket", feature(proc_macro_hygiene, decl_macro))]
+
+#[cfg(feature = "rocket")]
+#[macro_use]
+extern crate rocket;
+
+mod attributes;
+#[cfg(feature = "http")]
+mod client;
 mod constants;
+mod directory;
+mod dkg;
+#[cfg(feature = "rocket")]
+pub mod endpoints;
 mod errors;
+mod escrow;
+mod issuance;
+mod jwt;
+mod linked;
+#[cfg(feature = "http")]
+mod messages;
+mod presentation;
+mod proof;
+mod revocation;
 mod signature;
 mod signing;
+mod spent;
+mod threshold;
+mod token;
 mod user;
 mod verifying;
+#[cfg(feature = "wallet")]
+mod wallet;
+#[cfg(feature = "rocket")]
+pub mod web;
 
+pub use crate::attributes::*;
+#[cfg(feature = "http")]
+pub use crate::client::*;
 pub use crate::constants::*;
+pub use crate::directory::*;
+pub use crate::dkg::*;
 pub use crate::errors::*;
+pub use crate::escrow::*;
+pub use crate::issuance::*;
+pub use crate::jwt::*;
+pub use crate::linked::*;
+#[cfg(feature = "http")]
+pub use crate::messages::*;
+pub use crate::presentation::{NonRevocation, Presentation, PresentationPolicy, RateLimit, RateLimitTag};
+pub use crate::proof::Proof;
+pub use crate::revocation::*;
 pub use crate::signature::*;
 pub use crate::signing::*;
+pub use crate::spent::*;
+pub use crate::threshold::*;
+pub use crate::token::*;
 pub use crate::user::*;
 pub use crate::verifying::*;
+#[cfg(feature = "wallet")]
+pub use crate::wallet::*;
 
 pub fn add(left: u64, right: u64) -> u64 {
     left + right
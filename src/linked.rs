@@ -0,0 +1,231 @@
+use crate::attributes::Schema;
+use crate::errors::{UserError, VerifyingError};
+use crate::presentation::{delta_tag, relate};
+use crate::proof::{Proof, Statement, Transcript};
+use crate::signature::Signature;
+use crate::token::Token;
+use crate::verifying::VerifyingKey;
+
+use curve25519_dalek::constants::RISTRETTO_BASEPOINT_POINT;
+use curve25519_dalek::ristretto::RistrettoPoint;
+use curve25519_dalek::scalar::Scalar;
+use curve25519_dalek::traits::Identity;
+
+use rand_core::{CryptoRng, RngCore};
+
+use serde::{Deserialize, Serialize};
+
+// LinkPolicy is the per-credential part of a linked presentation's policy:
+// which attributes of the credential are revealed, and the index of its
+// hidden user secret. The secret index may differ between schemas, since each
+// issuer has its own.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct LinkPolicy {
+    pub revealed: Vec<usize>,
+    pub secret: usize,
+}
+
+// LinkedPart is what a linked presentation shows of one credential
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct LinkedPart {
+    pub signature: Signature,
+    pub blinded_commitment: RistrettoPoint,
+    pub hashed_message: Vec<u8>,
+    pub revealed: Vec<(usize, Scalar)>,
+    pub(crate) delta_tag: RistrettoPoint,
+}
+
+// LinkedPresentation shows several credentials at once, possibly from
+// different issuers, and proves that they all certify the same hidden user
+// secret. This lets a verifier combine e.g. a subscription from one issuer
+// with an age check from another, without learning the secret, while making
+// sure both credentials belong to the same user.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct LinkedPresentation {
+    pub parts: Vec<LinkedPart>,
+    pub(crate) proof: Proof,
+}
+
+impl LinkedPresentation {
+    pub fn new<R: RngCore + CryptoRng>(
+        rng: &mut R,
+        shows: &[(&Token, &VerifyingKey, &Schema, &LinkPolicy)],
+        context: &[u8],
+    ) -> Result<LinkedPresentation, UserError> {
+        let mut parts = Vec::with_capacity(shows.len());
+
+        for (token, _, schema, policy) in shows {
+            if token.opening.attributes.len() != schema.len() {
+                return Err(UserError::OpeningLength);
+            }
+
+            parts.push(LinkedPart {
+                signature: token.signature,
+                blinded_commitment: token.blinded_commitment,
+                hashed_message: token.hashed_message.clone(),
+                revealed: policy
+                    .revealed
+                    .iter()
+                    .map(|index| Ok((*index, *token.opening.attributes.get(*index).ok_or(UserError::AttributeIndex)?)))
+                    .collect::<Result<_, UserError>>()?,
+                delta_tag: delta_tag(token),
+            });
+        }
+
+        let mut presentation = LinkedPresentation {
+            parts,
+            proof: Proof::default(),
+        };
+
+        let verifier_shows: Vec<(&VerifyingKey, &Schema, &LinkPolicy)> =
+            shows.iter().map(|(_, key, schema, policy)| (*key, *schema, *policy)).collect();
+        let tokens: Vec<&Token> = shows.iter().map(|(token, _, _, _)| *token).collect();
+
+        let statement = presentation
+            .statement(&verifier_shows, Some(&tokens))
+            .ok_or(UserError::AttributeIndex)?;
+        presentation.proof = statement.prove(rng, presentation.transcript(&verifier_shows, context));
+
+        Ok(presentation)
+    }
+
+    pub fn verify(
+        &self,
+        shows: &[(&VerifyingKey, &Schema, &LinkPolicy)],
+        context: &[u8],
+    ) -> Result<(), VerifyingError> {
+        if self.parts.len() != shows.len() {
+            return Err(VerifyingError::AttributeIndex);
+        }
+
+        for (part, (key, _, policy)) in self.parts.iter().zip(shows.iter()) {
+            key.verify_prehashed(&part.hashed_message, &part.blinded_commitment, &part.signature)?;
+
+            if part.revealed.len() != policy.revealed.len()
+                || part
+                    .revealed
+                    .iter()
+                    .zip(policy.revealed.iter())
+                    .any(|((index, _), required)| index != required)
+            {
+                return Err(VerifyingError::AttributeIndex);
+            }
+
+            if part.delta_tag == RistrettoPoint::identity() {
+                return Err(VerifyingError::InvalidProof);
+            }
+        }
+
+        let statement = self.statement(shows, None).ok_or(VerifyingError::AttributeIndex)?;
+
+        if statement.verify(self.transcript(shows, context), &self.proof) {
+            Ok(())
+        } else {
+            Err(VerifyingError::InvalidProof)
+        }
+    }
+
+    // statement relates every part to its credential, and then shows
+    // secret_0*G - secret_i*G = 0 for every other part i
+    fn statement(
+        &self,
+        shows: &[(&VerifyingKey, &Schema, &LinkPolicy)],
+        tokens: Option<&[&Token]>,
+    ) -> Option<Statement> {
+        let mut statement = Statement::default();
+        let mut secrets = Vec::with_capacity(self.parts.len());
+
+        for (index, (part, (_, schema, policy))) in self.parts.iter().zip(shows.iter()).enumerate() {
+            let vars = relate(
+                &mut statement,
+                schema,
+                &part.blinded_commitment,
+                &part.delta_tag,
+                &part.revealed,
+                tokens.map(|tokens| tokens[index]),
+            )?;
+            secrets.push((*vars.attributes.get(policy.secret)?)?);
+        }
+
+        for secret in secrets.iter().skip(1) {
+            statement.constrain(
+                RistrettoPoint::identity(),
+                vec![(secrets[0], RISTRETTO_BASEPOINT_POINT), (*secret, -RISTRETTO_BASEPOINT_POINT)],
+            );
+        }
+
+        Some(statement)
+    }
+
+    fn transcript(&self, shows: &[(&VerifyingKey, &Schema, &LinkPolicy)], context: &[u8]) -> Transcript {
+        let mut transcript = Transcript::new(b"acl linked presentation");
+        for (part, (key, _, _)) in self.parts.iter().zip(shows.iter()) {
+            transcript.append_point(b"key", &key.point);
+            transcript.append_message(b"signature", &part.signature.to_bytes());
+            transcript.append_message(b"hashed message", &part.hashed_message);
+        }
+        transcript.append_message(b"context", context);
+        transcript
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::signing::SigningKey;
+    use crate::token::tests::issue;
+    use rand_core::OsRng;
+
+    #[test]
+    fn linked_credentials_share_a_secret() {
+        let publisher = SigningKey::from_bytes(&[7u8; 32]);
+        let age_checker = SigningKey::from_bytes(&[8u8; 32]);
+        let (publisher_key, age_checker_key) = (VerifyingKey::from(&publisher), VerifyingKey::from(&age_checker));
+
+        let subscription_schema = Schema::new(&["user secret", "paid"]);
+        let age_schema = Schema::new(&["over 18", "country", "user secret"]);
+        let secret = Scalar::random(&mut OsRng);
+
+        let subscription = issue(&publisher, &subscription_schema, vec![secret, Scalar::ONE]);
+        let age = issue(&age_checker, &age_schema, vec![Scalar::ONE, Scalar::from(49u8), secret]);
+        let someone_elses = issue(&age_checker, &age_schema, vec![Scalar::ONE, Scalar::from(49u8), Scalar::ONE]);
+
+        let subscription_policy = LinkPolicy { revealed: vec![1], secret: 0 };
+        let age_policy = LinkPolicy { revealed: vec![0], secret: 2 };
+        let shows = [
+            (&publisher_key, &subscription_schema, &subscription_policy),
+            (&age_checker_key, &age_schema, &age_policy),
+        ];
+
+        let presentation = LinkedPresentation::new(
+            &mut OsRng,
+            &[
+                (&subscription, &publisher_key, &subscription_schema, &subscription_policy),
+                (&age, &age_checker_key, &age_schema, &age_policy),
+            ],
+            b"nonce",
+        )
+        .unwrap();
+        assert_eq!(presentation.verify(&shows, b"nonce"), Ok(()));
+        assert_eq!(presentation.verify(&shows, b"other nonce"), Err(VerifyingError::InvalidProof));
+        assert_eq!(presentation.verify(&shows[..1], b"nonce"), Err(VerifyingError::AttributeIndex));
+
+        let swapped_keys = [
+            (&age_checker_key, &subscription_schema, &subscription_policy),
+            (&publisher_key, &age_schema, &age_policy),
+        ];
+        assert_eq!(presentation.verify(&swapped_keys, b"nonce"), Err(VerifyingError::Invalid));
+
+        // credentials of two different users can't be linked
+        let unlinked = LinkedPresentation::new(
+            &mut OsRng,
+            &[
+                (&subscription, &publisher_key, &subscription_schema, &subscription_policy),
+                (&someone_elses, &age_checker_key, &age_schema, &age_policy),
+            ],
+            b"nonce",
+        )
+        .unwrap();
+        assert_eq!(unlinked.verify(&shows, b"nonce"), Err(VerifyingError::InvalidProof));
+    }
+}
@@ -0,0 +1,156 @@
+// Wire format of the issuance endpoints (see the endpoints module), behind
+// the "http" feature.
+//
+// An issuance takes two round trips:
+//
+//   POST /acl/prepare  IssuanceRequest (JSON)  ->  PrepareResponse
+//   POST /acl/presign  PresignRequest          ->  PresignResponse
+//
+// Messages are JSON with base64 byte strings, or with Content-Type (for
+// bodies) or Accept (for responses) application/octet-stream, the raw bytes
+// from to_bytes. The IssuanceRequest itself is always JSON.
+use curve25519_dalek::scalar::Scalar;
+
+use serde::{Deserialize, Serialize};
+
+pub const PREPARE_PATH: &str = "/acl/prepare";
+pub const PRESIGN_PATH: &str = "/acl/presign";
+
+pub const SESSION_LENGTH: usize = 32;
+
+// PrepareResponse carries the signer's prepare message, the session it
+// belongs to, and the values the issuer assigned at the request's
+// issuer_assigned indices, which the user stores in their opening
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct PrepareResponse {
+    #[serde(with = "base64_bytes")]
+    pub session: Vec<u8>,
+    #[serde(with = "base64_bytes")]
+    pub message: Vec<u8>,
+    pub issuer_attributes: Vec<(usize, Scalar)>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct PresignRequest {
+    #[serde(with = "base64_bytes")]
+    pub session: Vec<u8>,
+    #[serde(with = "base64_bytes")]
+    pub challenge: Vec<u8>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct PresignResponse {
+    #[serde(with = "base64_bytes")]
+    pub presignature: Vec<u8>,
+}
+
+impl PrepareResponse {
+    // to_bytes is the session, the 128-byte prepare message, then every
+    // issuer attribute as a little-endian u32 index and a 32-byte scalar
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = [self.session.as_slice(), self.message.as_slice()].concat();
+        for (index, value) in self.issuer_attributes.iter() {
+            bytes.extend_from_slice(&(*index as u32).to_le_bytes());
+            bytes.extend_from_slice(value.as_bytes());
+        }
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<PrepareResponse> {
+        let attributes = bytes.get(SESSION_LENGTH + 128..)?;
+        if attributes.len() % 36 != 0 {
+            return None;
+        }
+
+        Some(PrepareResponse {
+            session: bytes[..SESSION_LENGTH].to_vec(),
+            message: bytes[SESSION_LENGTH..SESSION_LENGTH + 128].to_vec(),
+            issuer_attributes: attributes
+                .chunks_exact(36)
+                .map(|chunk| {
+                    let index = u32::from_le_bytes(chunk[..4].try_into().ok()?) as usize;
+                    let value = Scalar::from_canonical_bytes(chunk[4..].try_into().ok()?).into_option()?;
+                    Some((index, value))
+                })
+                .collect::<Option<_>>()?,
+        })
+    }
+}
+
+impl PresignRequest {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        [self.session.as_slice(), self.challenge.as_slice()].concat()
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<PresignRequest> {
+        if bytes.len() != SESSION_LENGTH + 32 {
+            return None;
+        }
+
+        Some(PresignRequest {
+            session: bytes[..SESSION_LENGTH].to_vec(),
+            challenge: bytes[SESSION_LENGTH..].to_vec(),
+        })
+    }
+}
+
+impl PresignResponse {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.presignature.clone()
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<PresignResponse> {
+        if bytes.len() != 160 {
+            return None;
+        }
+
+        Some(PresignResponse {
+            presignature: bytes.to_vec(),
+        })
+    }
+}
+
+// base64_bytes (de)serializes byte strings as standard base64
+mod base64_bytes {
+    use base64::engine::general_purpose::STANDARD;
+    use base64::Engine;
+
+    use serde::{de, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&STANDARD.encode(bytes))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        STANDARD
+            .decode(String::deserialize(deserializer)?)
+            .map_err(de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn binary_and_json_encodings_round_trip() {
+        let prepare = PrepareResponse {
+            session: vec![1u8; SESSION_LENGTH],
+            message: vec![2u8; 128],
+            issuer_attributes: vec![(3, Scalar::from(42u8))],
+        };
+        let presign = PresignRequest {
+            session: vec![1u8; SESSION_LENGTH],
+            challenge: vec![4u8; 32],
+        };
+
+        assert_eq!(PrepareResponse::from_bytes(&prepare.to_bytes()), Some(prepare.clone()));
+        assert_eq!(PresignRequest::from_bytes(&presign.to_bytes()), Some(presign.clone()));
+        assert_eq!(PrepareResponse::from_bytes(&prepare.to_bytes()[1..]), None);
+        assert_eq!(PresignRequest::from_bytes(&presign.to_bytes()[1..]), None);
+
+        let json = serde_json::to_string(&presign).unwrap();
+        assert!(json.contains("\"challenge\":\"BAQE"));
+        assert_eq!(serde_json::from_str::<PresignRequest>(&json).unwrap(), presign);
+    }
+}
@@ -0,0 +1,641 @@
+use crate::attributes::Schema;
+use crate::constants::{gen_h, gen_h_table};
+use crate::escrow::{Ciphertext, TrusteePublicKey};
+use crate::errors::{UserError, VerifyingError};
+use crate::proof::{Proof, Statement, Transcript};
+use crate::revocation::{RevocationList, RevocationVerifyingKey};
+use crate::signature::Signature;
+use crate::token::Token;
+use crate::verifying::VerifyingKey;
+
+use curve25519_dalek::constants::RISTRETTO_BASEPOINT_POINT;
+use curve25519_dalek::ristretto::RistrettoPoint;
+use curve25519_dalek::scalar::Scalar;
+use curve25519_dalek::traits::Identity;
+
+use rand_core::{CryptoRng, RngCore};
+
+use serde::{Deserialize, Serialize};
+
+use sha2::{Digest, Sha512};
+
+// PresentationPolicy is what a verifier asks of a presentation, and what the
+// user follows to build one: which attributes are revealed, and which
+// extensions are attached.
+//
+// With a pseudonym (secret index, scope), the presentation carries
+// nym = secret * P_scope for the hidden attribute at secret index, where
+// P_scope is hashed from the scope. The nym is stable for one secret and
+// scope, so a site can rate-limit or ban it, but nyms for different scopes
+// can't be linked. Since showing the same credential twice links the two
+// shows through its signature, users should present a fresh credential
+// (issued with the same secret) each time.
+//
+// With a rate limit, see RateLimit, the presentation carries a tag that
+// repeats once a secret is shown more than limit times in one epoch.
+//
+// With escrow (user id index, trustee), the presentation carries an ElGamal
+// encryption of the hidden user id attribute to the trustee, proved to match
+// the certified commitment, so the trustee can de-anonymize it with
+// TrusteeKey::open.
+//
+// With revocation (handle index, revocation key, list), the presentation
+// proves that its hidden revocation handle is not on the list, see
+// NonRevocation. Verification rejects a list that isn't signed under the
+// revocation key.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct PresentationPolicy {
+    pub revealed: Vec<usize>,
+    pub pseudonym: Option<(usize, Vec<u8>)>,
+    pub rate_limit: Option<RateLimit>,
+    pub escrow: Option<(usize, TrusteePublicKey)>,
+    pub revocation: Option<(usize, RevocationVerifyingKey, RevocationList)>,
+}
+
+// RateLimit allows limit shows per epoch of the hidden attribute at secret.
+// The j-th show of an epoch (0 <= j < limit) carries tag = secret * P_j, with
+// P_j hashed from the epoch and j, and proves in zero knowledge that j is in
+// range without revealing it. A user who shows more than limit times in one
+// epoch must repeat a tag, which the verifier's tag store catches (see
+// Verifier::verify_presentation). The issuer has to make sure each user only
+// ever gets credentials for one secret. Proofs grow linearly with limit.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RateLimit {
+    pub secret: usize,
+    pub epoch: Vec<u8>,
+    pub limit: u32,
+}
+
+// RateLimitTag is the rate limit extension of a presentation: the tag, and a
+// commitment secret*G + t*H that the zero-knowledge range of tags is proved
+// against
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct RateLimitTag {
+    pub tag: RistrettoPoint,
+    pub commitment: RistrettoPoint,
+}
+
+// NonRevocation is the revocation extension of a presentation, a tag
+// handle * base for a fresh random base. Proving the tag well-formed and
+// checking tag != revoked * base for every revoked handle shows that the
+// handle isn't revoked, without revealing it (Camenisch-Shoup style).
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct NonRevocation {
+    pub base: RistrettoPoint,
+    pub tag: RistrettoPoint,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Presentation {
+    pub signature: Signature,
+    pub blinded_commitment: RistrettoPoint,
+    pub hashed_message: Vec<u8>,
+    pub revealed: Vec<(usize, Scalar)>,
+    pub pseudonym: Option<RistrettoPoint>,
+    pub rate_limit: Option<RateLimitTag>,
+    pub escrow: Option<Ciphertext>,
+    pub revocation: Option<NonRevocation>,
+    pub(crate) delta_tag: RistrettoPoint,
+    pub(crate) proof: Proof,
+}
+
+// Witness is what only the prover knows about a presentation
+struct Witness<'a> {
+    token: &'a Token,
+    show: u32,
+    tag_blinding: Scalar,
+    escrow_randomness: Scalar,
+}
+
+impl Presentation {
+    pub fn new<R: RngCore + CryptoRng>(
+        rng: &mut R,
+        token: &Token,
+        key: &VerifyingKey,
+        schema: &Schema,
+        policy: &PresentationPolicy,
+        context: &[u8],
+    ) -> Result<Presentation, UserError> {
+        Presentation::create(rng, token, key, schema, policy, None, context)
+    }
+
+    // new_rate_limited is new for policies with a rate limit, where show is
+    // the number of earlier shows of the secret in the policy's epoch
+    pub fn new_rate_limited<R: RngCore + CryptoRng>(
+        rng: &mut R,
+        token: &Token,
+        key: &VerifyingKey,
+        schema: &Schema,
+        policy: &PresentationPolicy,
+        show: u32,
+        context: &[u8],
+    ) -> Result<Presentation, UserError> {
+        Presentation::create(rng, token, key, schema, policy, Some(show), context)
+    }
+
+    fn create<R: RngCore + CryptoRng>(
+        rng: &mut R,
+        token: &Token,
+        key: &VerifyingKey,
+        schema: &Schema,
+        policy: &PresentationPolicy,
+        show: Option<u32>,
+        context: &[u8],
+    ) -> Result<Presentation, UserError> {
+        if token.opening.attributes.len() != schema.len() {
+            return Err(UserError::OpeningLength);
+        }
+
+        let attribute = |index: &usize| token.opening.attributes.get(*index).ok_or(UserError::AttributeIndex);
+
+        let revealed: Vec<(usize, Scalar)> = policy
+            .revealed
+            .iter()
+            .map(|index| Ok((*index, *attribute(index)?)))
+            .collect::<Result<_, UserError>>()?;
+
+        let pseudonym = match &policy.pseudonym {
+            Some((index, scope)) => Some(pseudonym_base(scope) * attribute(index)?),
+            None => None,
+        };
+
+        let witness = Witness {
+            token,
+            show: show.unwrap_or(0),
+            tag_blinding: Scalar::random(rng),
+            escrow_randomness: Scalar::random(rng),
+        };
+
+        let escrow = match &policy.escrow {
+            Some((index, trustee)) => Some(trustee.encrypt(attribute(index)?, &witness.escrow_randomness)),
+            None => None,
+        };
+
+        let revocation = match &policy.revocation {
+            Some((index, _, list)) => {
+                let handle = attribute(index)?;
+                if list.contains(handle) {
+                    return Err(UserError::Revoked);
+                }
+                let base = RistrettoPoint::random(rng);
+                Some(NonRevocation {
+                    base,
+                    tag: base * handle,
+                })
+            }
+            None => None,
+        };
+
+        let rate_limit = match (&policy.rate_limit, show) {
+            (Some(rate_limit), Some(show)) if show < rate_limit.limit => {
+                let secret = attribute(&rate_limit.secret)?;
+                Some(RateLimitTag {
+                    tag: tag_base(&rate_limit.epoch, show) * secret,
+                    commitment: RistrettoPoint::mul_base(secret) + gen_h_table() * &witness.tag_blinding,
+                })
+            }
+            (None, None) => None,
+            _ => return Err(UserError::ShowIndex),
+        };
+
+        let mut presentation = Presentation {
+            signature: token.signature,
+            blinded_commitment: token.blinded_commitment,
+            hashed_message: token.hashed_message.clone(),
+            revealed,
+            pseudonym,
+            rate_limit,
+            escrow,
+            revocation,
+            delta_tag: delta_tag(token),
+            proof: Proof::default(),
+        };
+
+        let (statement, alternatives) = presentation
+            .statement(schema, policy, Some(&witness))
+            .ok_or(UserError::AttributeIndex)?;
+        presentation.proof =
+            statement.prove_or(&alternatives, witness.show as usize, rng, presentation.transcript(key, context));
+
+        Ok(presentation)
+    }
+
+    pub fn verify(
+        &self,
+        key: &VerifyingKey,
+        schema: &Schema,
+        policy: &PresentationPolicy,
+        context: &[u8],
+    ) -> Result<(), VerifyingError> {
+        key.verify_prehashed(&self.hashed_message, &self.blinded_commitment, &self.signature)?;
+
+        if self.revealed.len() != policy.revealed.len()
+            || self
+                .revealed
+                .iter()
+                .zip(policy.revealed.iter())
+                .any(|((index, _), required)| index != required)
+        {
+            return Err(VerifyingError::AttributeIndex);
+        }
+
+        // an identity delta_tag can only come from proving with delta = 0, see
+        // relate
+        if self.delta_tag == RistrettoPoint::identity()
+            || self.pseudonym.is_some() != policy.pseudonym.is_some()
+            || self.rate_limit.is_some() != policy.rate_limit.is_some()
+            || self.escrow.is_some() != policy.escrow.is_some()
+            || self.revocation.is_some() != policy.revocation.is_some()
+        {
+            return Err(VerifyingError::InvalidProof);
+        }
+
+        let (statement, alternatives) = self.statement(schema, policy, None).ok_or(VerifyingError::AttributeIndex)?;
+
+        if !statement.verify_or(&alternatives, self.transcript(key, context), &self.proof) {
+            return Err(VerifyingError::InvalidProof);
+        }
+
+        match (&policy.revocation, &self.revocation) {
+            (Some((_, revocation_key, list)), Some(revocation)) => {
+                list.verify(revocation_key)?;
+                if list.excludes(&revocation.base, &revocation.tag) {
+                    Ok(())
+                } else {
+                    Err(VerifyingError::Revoked)
+                }
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // statement builds the credential relation plus every extension in the
+    // policy. Extensions that need an OR-proof add alternatives, of which the
+    // prover's witness fills in the one at witness.show.
+    fn statement(
+        &self,
+        schema: &Schema,
+        policy: &PresentationPolicy,
+        witness: Option<&Witness>,
+    ) -> Option<(Statement, Vec<Statement>)> {
+        let mut statement = Statement::default();
+        let mut alternatives = Vec::new();
+        let vars = relate(
+            &mut statement,
+            schema,
+            &self.blinded_commitment,
+            &self.delta_tag,
+            &self.revealed,
+            witness.map(|w| w.token),
+        )?;
+
+        if let (Some((index, scope)), Some(nym)) = (&policy.pseudonym, &self.pseudonym) {
+            let secret = (*vars.attributes.get(*index)?)?;
+            statement.constrain(*nym, vec![(secret, pseudonym_base(scope))]);
+        }
+
+        if let (Some((index, trustee)), Some(ciphertext)) = (&policy.escrow, &self.escrow) {
+            let user_id = (*vars.attributes.get(*index)?)?;
+            let randomness = statement.allocate(witness.map(|w| w.escrow_randomness).unwrap_or(Scalar::ZERO));
+            statement.constrain(ciphertext.c1, vec![(randomness, RISTRETTO_BASEPOINT_POINT)]);
+            statement.constrain(
+                ciphertext.c2,
+                vec![(user_id, RISTRETTO_BASEPOINT_POINT), (randomness, trustee.point)],
+            );
+        }
+
+        if let (Some((index, _, _)), Some(revocation)) = (&policy.revocation, &self.revocation) {
+            let handle = (*vars.attributes.get(*index)?)?;
+            statement.constrain(revocation.tag, vec![(handle, revocation.base)]);
+        }
+
+        if let (Some(rate_limit), Some(tag)) = (&policy.rate_limit, &self.rate_limit) {
+            let secret = (*vars.attributes.get(rate_limit.secret)?)?;
+            let tag_blinding = statement.allocate(witness.map(|w| w.tag_blinding).unwrap_or(Scalar::ZERO));
+            statement.constrain(
+                tag.commitment,
+                vec![(secret, RISTRETTO_BASEPOINT_POINT), (tag_blinding, *gen_h())],
+            );
+
+            for show in 0..rate_limit.limit {
+                let real = witness.filter(|w| w.show == show);
+                let secret_value = real.map(|w| w.token.opening.attributes[rate_limit.secret]);
+
+                let mut alternative = Statement::default();
+                let secret = alternative.allocate(secret_value.unwrap_or(Scalar::ZERO));
+                let tag_blinding = alternative.allocate(real.map(|w| w.tag_blinding).unwrap_or(Scalar::ZERO));
+                alternative.constrain(
+                    tag.commitment,
+                    vec![(secret, RISTRETTO_BASEPOINT_POINT), (tag_blinding, *gen_h())],
+                );
+                alternative.constrain(tag.tag, vec![(secret, tag_base(&rate_limit.epoch, show))]);
+                alternatives.push(alternative);
+            }
+        }
+
+        Some((statement, alternatives))
+    }
+
+    fn transcript(&self, key: &VerifyingKey, context: &[u8]) -> Transcript {
+        transcript(b"acl presentation", key, &self.signature, &self.hashed_message, context)
+    }
+}
+
+fn tag_base(epoch: &[u8], show: u32) -> RistrettoPoint {
+    let mut hash = Sha512::new();
+    hash.update(b"acl rate limit tag");
+    hash.update((epoch.len() as u64).to_le_bytes());
+    hash.update(epoch);
+    hash.update(show.to_le_bytes());
+    RistrettoPoint::from_hash(hash)
+}
+
+fn pseudonym_base(scope: &[u8]) -> RistrettoPoint {
+    let mut hash = Sha512::new();
+    hash.update(b"acl pseudonym scope");
+    hash.update(scope);
+    RistrettoPoint::from_hash(hash)
+}
+
+// CredentialVars are the statement variables allocated for one credential:
+// its blinding factor and each hidden attribute (None for revealed ones)
+pub(crate) struct CredentialVars {
+    pub(crate) blinding: usize,
+    pub(crate) attributes: Vec<Option<usize>>,
+}
+
+// relate adds the relation between a blinded commitment and the attributes it
+// certifies to a statement. compute_signature returns a credential with
+// blinded = gamma * (C + rnd*G) with C = r*H + sum(m_i * G_i), so with
+// delta = 1/gamma the prover shows
+//
+//   sum(revealed m_i * G_i) = delta*blinded - r*H - rnd*G - sum(hidden m_j * G_j)
+//
+// rnd stays hidden because the signer chose it and would recognise it. The
+// prover passes its token to fill in the witnesses. It returns None if the
+// revealed indices are out of range or not strictly increasing, or the token
+// doesn't match the schema.
+//
+// delta = 0 with every other witness zero satisfies the relation for any
+// blinded commitment, so relate also proves delta_tag = delta * B for a base B
+// hashed from the blinded commitment, see delta_tag. Verifiers must reject an
+// identity delta_tag.
+pub(crate) fn relate(
+    statement: &mut Statement,
+    schema: &Schema,
+    blinded_commitment: &RistrettoPoint,
+    delta_tag: &RistrettoPoint,
+    revealed: &[(usize, Scalar)],
+    token: Option<&Token>,
+) -> Option<CredentialVars> {
+    if revealed.windows(2).any(|pair| pair[0].0 >= pair[1].0)
+        || revealed.iter().any(|(index, _)| *index >= schema.len())
+        || token.is_some_and(|token| token.opening.attributes.len() != schema.len())
+    {
+        return None;
+    }
+
+    let witness = |value: fn(&Token) -> Scalar| token.map(value).unwrap_or(Scalar::ZERO);
+
+    let delta = statement.allocate(witness(|token| token.gamma.invert()));
+    let blinding = statement.allocate(witness(|token| token.opening.blinding));
+    let rnd = statement.allocate(witness(|token| token.rnd));
+
+    statement.constrain(*delta_tag, vec![(delta, delta_base(blinded_commitment))]);
+
+    let mut lhs = RistrettoPoint::default();
+    let mut terms = vec![
+        (delta, *blinded_commitment),
+        (blinding, -gen_h()),
+        (rnd, -RISTRETTO_BASEPOINT_POINT),
+    ];
+    let mut attributes = Vec::with_capacity(schema.len());
+
+    for (index, generator) in schema.generators.iter().enumerate() {
+        match revealed.iter().find(|(revealed_index, _)| *revealed_index == index) {
+            Some((_, value)) => {
+                lhs += generator * value;
+                attributes.push(None);
+            }
+            None => {
+                let var = statement.allocate(token.map(|token| token.opening.attributes[index]).unwrap_or(Scalar::ZERO));
+                terms.push((var, -generator));
+                attributes.push(Some(var));
+            }
+        }
+    }
+
+    statement.constrain(lhs, terms);
+    Some(CredentialVars { blinding, attributes })
+}
+
+// delta_tag is the token's delta = 1/gamma times a base hashed from its blinded
+// commitment. It is fixed per token, like the signature, so it links nothing
+// that the signature doesn't already link.
+pub(crate) fn delta_tag(token: &Token) -> RistrettoPoint {
+    delta_base(&token.blinded_commitment) * token.gamma.invert()
+}
+
+fn delta_base(blinded_commitment: &RistrettoPoint) -> RistrettoPoint {
+    let mut hash = Sha512::new();
+    hash.update(b"acl delta base");
+    hash.update(blinded_commitment.compress().as_bytes());
+    RistrettoPoint::from_hash(hash)
+}
+
+// transcript starts the transcript of a proof about a credential, binding it
+// to the issuer's key, the signature and its message, and the verifier's
+// context (e.g. a nonce or the name of the service)
+pub(crate) fn transcript(
+    label: &[u8],
+    key: &VerifyingKey,
+    signature: &Signature,
+    hashed_message: &[u8],
+    context: &[u8],
+) -> Transcript {
+    let mut transcript = Transcript::new(label);
+    transcript.append_point(b"key", &key.point);
+    transcript.append_message(b"signature", &signature.to_bytes());
+    transcript.append_message(b"hashed message", hashed_message);
+    transcript.append_message(b"context", context);
+    transcript
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::escrow::TrusteeKey;
+    use crate::revocation::RevocationKey;
+    use crate::signing::SigningKey;
+    use crate::token::tests::{issue, schema};
+    use rand_core::OsRng;
+
+    fn attributes() -> Vec<Scalar> {
+        vec![Scalar::from(987654321u32), Scalar::from(2u8), Scalar::ONE, Scalar::ZERO]
+    }
+
+    fn policy(scope: &[u8]) -> PresentationPolicy {
+        PresentationPolicy {
+            revealed: vec![2],
+            pseudonym: Some((0, scope.to_vec())),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn pseudonyms_are_stable_per_scope() {
+        let signing_key = SigningKey::from_bytes(&[7u8; 32]);
+        let key = VerifyingKey::from(&signing_key);
+        let first = issue(&signing_key, &schema(), attributes());
+        let second = issue(&signing_key, &schema(), attributes());
+
+        let present = |token: &Token, scope: &[u8]| {
+            let presentation = Presentation::new(&mut OsRng, token, &key, &schema(), &policy(scope), b"nonce").unwrap();
+            assert_eq!(presentation.verify(&key, &schema(), &policy(scope), b"nonce"), Ok(()));
+            presentation
+        };
+
+        let forum = present(&first, b"forum.example");
+        assert_eq!(forum.revealed, vec![(2, Scalar::ONE)]);
+        assert_eq!(forum.pseudonym, present(&second, b"forum.example").pseudonym);
+        assert_ne!(forum.pseudonym, present(&first, b"news.example").pseudonym);
+
+        let mut other_attributes = attributes();
+        other_attributes[0] += Scalar::ONE;
+        let other = issue(&signing_key, &schema(), other_attributes);
+        assert_ne!(forum.pseudonym, present(&other, b"forum.example").pseudonym);
+    }
+
+    #[test]
+    fn pseudonyms_must_match_the_policy() {
+        let signing_key = SigningKey::from_bytes(&[7u8; 32]);
+        let key = VerifyingKey::from(&signing_key);
+        let token = issue(&signing_key, &schema(), attributes());
+        let presentation = Presentation::new(&mut OsRng, &token, &key, &schema(), &policy(b"forum"), b"nonce").unwrap();
+
+        assert_eq!(
+            presentation.verify(&key, &schema(), &policy(b"other forum"), b"nonce"),
+            Err(VerifyingError::InvalidProof)
+        );
+        assert_eq!(
+            presentation.verify(&key, &schema(), &policy(b"forum"), b"other nonce"),
+            Err(VerifyingError::InvalidProof)
+        );
+        assert_eq!(
+            presentation.verify(&key, &schema(), &PresentationPolicy::default(), b"nonce"),
+            Err(VerifyingError::AttributeIndex)
+        );
+
+        let mut swapped = presentation.clone();
+        swapped.pseudonym = Some(pseudonym_base(b"forum") * Scalar::from(5u8));
+        assert_eq!(
+            swapped.verify(&key, &schema(), &policy(b"forum"), b"nonce"),
+            Err(VerifyingError::InvalidProof)
+        );
+
+        let mut identity = presentation.clone();
+        identity.pseudonym = Some(RistrettoPoint::identity());
+        assert_eq!(
+            identity.verify(&key, &schema(), &policy(b"forum"), b"nonce"),
+            Err(VerifyingError::InvalidProof)
+        );
+
+        let mut revealed_secret = policy(b"forum");
+        revealed_secret.revealed = vec![0];
+        assert_eq!(
+            Presentation::new(&mut OsRng, &token, &key, &schema(), &revealed_secret, b"nonce").unwrap_err(),
+            UserError::AttributeIndex
+        );
+    }
+
+    #[test]
+    fn trustee_opens_escrowed_user_ids() {
+        let signing_key = SigningKey::from_bytes(&[7u8; 32]);
+        let key = VerifyingKey::from(&signing_key);
+        let trustee = TrusteeKey::random(&mut OsRng);
+        let token = issue(&signing_key, &schema(), attributes());
+
+        let policy = PresentationPolicy {
+            revealed: vec![1],
+            escrow: Some((0, trustee.public_key())),
+            ..Default::default()
+        };
+        let presentation = Presentation::new(&mut OsRng, &token, &key, &schema(), &policy, b"nonce").unwrap();
+        assert_eq!(presentation.verify(&key, &schema(), &policy, b"nonce"), Ok(()));
+
+        let ciphertext = presentation.escrow.unwrap();
+        assert!(trustee.identifies(&ciphertext, &attributes()[0]));
+        assert!(!trustee.identifies(&ciphertext, &(attributes()[0] + Scalar::ONE)));
+        assert!(!TrusteeKey::random(&mut OsRng).identifies(&ciphertext, &attributes()[0]));
+
+        // the ciphertext can't be swapped for one of another id, or for one
+        // to another trustee
+        let mut swapped = presentation.clone();
+        swapped.escrow = Some(trustee.public_key().encrypt(&Scalar::from(5u8), &Scalar::from(6u8)));
+        assert_eq!(swapped.verify(&key, &schema(), &policy, b"nonce"), Err(VerifyingError::InvalidProof));
+
+        let mut other_trustee = policy.clone();
+        other_trustee.escrow = Some((0, TrusteeKey::random(&mut OsRng).public_key()));
+        assert_eq!(
+            presentation.verify(&key, &schema(), &other_trustee, b"nonce"),
+            Err(VerifyingError::InvalidProof)
+        );
+
+        let mut without = presentation.clone();
+        without.escrow = None;
+        assert_eq!(without.verify(&key, &schema(), &policy, b"nonce"), Err(VerifyingError::InvalidProof));
+    }
+
+    #[test]
+    fn revoked_handles_are_rejected() {
+        let signing_key = SigningKey::from_bytes(&[7u8; 32]);
+        let key = VerifyingKey::from(&signing_key);
+        let revocation_key = RevocationKey::random(&mut OsRng);
+
+        // the issuer assigns a random handle to attribute 3
+        let mut attributes = attributes();
+        attributes[3] = Scalar::random(&mut OsRng);
+        let token = issue(&signing_key, &schema(), attributes.clone());
+        let others: Vec<Scalar> = (0..4).map(|_| Scalar::random(&mut OsRng)).collect();
+
+        let policy = |list: RevocationList| PresentationPolicy {
+            revealed: vec![1],
+            revocation: Some((3, revocation_key.verifying_key(), list)),
+            ..Default::default()
+        };
+
+        let current = revocation_key.sign(&mut OsRng, 1, others.clone());
+        assert_eq!(current.verify(&revocation_key.verifying_key()), Ok(()));
+        let presentation = Presentation::new(&mut OsRng, &token, &key, &schema(), &policy(current), b"nonce").unwrap();
+
+        let next = revocation_key.sign(&mut OsRng, 2, [others.clone(), vec![attributes[3]]].concat());
+        assert_eq!(next.verify(&revocation_key.verifying_key()), Ok(()));
+        assert_eq!(
+            presentation.verify(&key, &schema(), &policy(next.clone()), b"nonce"),
+            Err(VerifyingError::Revoked)
+        );
+        assert_eq!(
+            Presentation::new(&mut OsRng, &token, &key, &schema(), &policy(next.clone()), b"nonce").unwrap_err(),
+            UserError::Revoked
+        );
+
+        let mut forged = next.clone();
+        forged.revoked.pop();
+        assert_eq!(forged.verify(&revocation_key.verifying_key()), Err(VerifyingError::InvalidProof));
+        assert_eq!(
+            presentation.verify(&key, &schema(), &policy(forged), b"nonce"),
+            Err(VerifyingError::InvalidProof)
+        );
+
+        // a list signed by anyone but the revocation key is no better
+        let attacker = RevocationKey::random(&mut OsRng).sign(&mut OsRng, 3, others.clone());
+        assert_eq!(
+            presentation.verify(&key, &schema(), &policy(attacker), b"nonce"),
+            Err(VerifyingError::InvalidProof)
+        );
+        assert_eq!(
+            next.verify(&RevocationKey::random(&mut OsRng).verifying_key()),
+            Err(VerifyingError::InvalidProof)
+        );
+    }
+}
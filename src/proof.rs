@@ -0,0 +1,264 @@
+use curve25519_dalek::constants::RISTRETTO_BASEPOINT_POINT;
+use curve25519_dalek::ristretto::RistrettoPoint;
+use curve25519_dalek::scalar::Scalar;
+use curve25519_dalek::traits::{MultiscalarMul, VartimeMultiscalarMul};
+
+use rand_core::{CryptoRng, RngCore};
+
+use serde::{Deserialize, Serialize};
+
+use sha2::{Digest, Sha512};
+
+// Transcript accumulates the public inputs of a Fiat-Shamir proof. Every item
+// is length-prefixed and the transcript starts with a label naming the kind of
+// proof, so that a proof for one statement can't be replayed as another.
+#[derive(Clone)]
+pub(crate) struct Transcript {
+    hash: Sha512,
+}
+
+impl Transcript {
+    pub(crate) fn new(label: &[u8]) -> Self {
+        let mut transcript = Transcript { hash: Sha512::new() };
+        transcript.append_message(b"acl proof", label);
+        transcript
+    }
+
+    pub(crate) fn append_message(&mut self, label: &[u8], message: &[u8]) {
+        self.hash.update((label.len() as u64).to_le_bytes());
+        self.hash.update(label);
+        self.hash.update((message.len() as u64).to_le_bytes());
+        self.hash.update(message);
+    }
+
+    pub(crate) fn append_point(&mut self, label: &[u8], point: &RistrettoPoint) {
+        self.append_message(label, point.compress().as_bytes());
+    }
+
+    pub(crate) fn challenge(self) -> Scalar {
+        Scalar::from_hash(self.hash)
+    }
+}
+
+struct Equation {
+    lhs: RistrettoPoint,
+    terms: Vec<(usize, RistrettoPoint)>,
+}
+
+// Statement is a conjunction of linear relations of the form
+// lhs = sum(witness_i * base_i) over secret witnesses, which is all the
+// zero-knowledge proofs in this crate need. Witnesses are allocated as
+// variables so that several relations can share one secret; the verifier
+// builds the same statement with Scalar::ZERO in place of every witness.
+#[derive(Default)]
+pub(crate) struct Statement {
+    equations: Vec<Equation>,
+    witnesses: Vec<Scalar>,
+}
+
+impl Statement {
+    // discrete_log is the statement public = secret*G, i.e. a Schnorr proof of
+    // knowledge; the verifier passes Scalar::ZERO as the secret
+    pub(crate) fn discrete_log(public: RistrettoPoint, secret: Scalar) -> Statement {
+        let mut statement = Statement::default();
+        let secret = statement.allocate(secret);
+        statement.constrain(public, vec![(secret, RISTRETTO_BASEPOINT_POINT)]);
+        statement
+    }
+
+    pub(crate) fn allocate(&mut self, witness: Scalar) -> usize {
+        self.witnesses.push(witness);
+        self.witnesses.len() - 1
+    }
+
+    pub(crate) fn constrain(&mut self, lhs: RistrettoPoint, terms: Vec<(usize, RistrettoPoint)>) {
+        self.equations.push(Equation { lhs, terms });
+    }
+
+    fn append_to(&self, transcript: &mut Transcript) {
+        for equation in self.equations.iter() {
+            transcript.append_point(b"lhs", &equation.lhs);
+            for (var, base) in equation.terms.iter() {
+                transcript.append_message(b"var", &(*var as u64).to_le_bytes());
+                transcript.append_point(b"base", base);
+            }
+        }
+    }
+
+    // commit computes the prover's first message from a nonce per variable;
+    // the nonces are secret, so this is constant-time
+    fn commit(&self, nonces: &[Scalar]) -> Vec<RistrettoPoint> {
+        self.equations
+            .iter()
+            .map(|equation| {
+                RistrettoPoint::multiscalar_mul(
+                    equation.terms.iter().map(|(var, _)| nonces[*var]),
+                    equation.terms.iter().map(|(_, base)| base),
+                )
+            })
+            .collect()
+    }
+
+    // recompute derives the prover's first message from the responses and the
+    // challenge, as sum(response_i * base_i) - challenge * lhs
+    fn recompute(&self, responses: &[Scalar], challenge: &Scalar) -> Vec<RistrettoPoint> {
+        self.equations
+            .iter()
+            .map(|equation| {
+                RistrettoPoint::vartime_multiscalar_mul(
+                    equation
+                        .terms
+                        .iter()
+                        .map(|(var, _)| responses[*var])
+                        .chain([-challenge]),
+                    equation
+                        .terms
+                        .iter()
+                        .map(|(_, base)| base)
+                        .chain([&equation.lhs]),
+                )
+            })
+            .collect()
+    }
+
+    // challenge_for is the challenge of a proof of this statement, without
+    // alternatives, whose first message is commitments. It is for provers
+    // that compute the first message and the responses themselves, like the
+    // nodes of a threshold key.
+    pub(crate) fn challenge_for(&self, mut transcript: Transcript, commitments: &[RistrettoPoint]) -> Scalar {
+        self.append_to(&mut transcript);
+        for commitment in commitments {
+            transcript.append_point(b"commitment", commitment);
+        }
+        transcript.challenge()
+    }
+
+    pub(crate) fn prove<R: RngCore + CryptoRng>(&self, rng: &mut R, transcript: Transcript) -> Proof {
+        self.prove_or(&[], 0, rng, transcript)
+    }
+
+    pub(crate) fn verify(&self, transcript: Transcript, proof: &Proof) -> bool {
+        self.verify_or(&[], transcript, proof)
+    }
+
+    // prove_or proves this statement together with at least one of the
+    // alternatives, namely alternatives[real], which must have its witnesses
+    // filled in. The other alternatives are simulated as in
+    // Cramer-Damgard-Schoenmakers: their challenges and responses are picked
+    // at random up front, and the real alternative's challenge is whatever
+    // makes all of them sum to the overall challenge.
+    pub(crate) fn prove_or<R: RngCore + CryptoRng>(
+        &self,
+        alternatives: &[Statement],
+        real: usize,
+        rng: &mut R,
+        mut transcript: Transcript,
+    ) -> Proof {
+        let nonces: Vec<Scalar> = self.witnesses.iter().map(|_| Scalar::random(rng)).collect();
+        let mut real_nonces = Vec::new();
+
+        let mut simulated: Vec<Proof> = Vec::with_capacity(alternatives.len());
+        let mut commitments = self.commit(&nonces);
+
+        for (index, alternative) in alternatives.iter().enumerate() {
+            if index == real {
+                real_nonces = alternative.witnesses.iter().map(|_| Scalar::random(rng)).collect();
+                commitments.extend(alternative.commit(&real_nonces));
+                simulated.push(Proof::default());
+            } else {
+                let challenge = Scalar::random(rng);
+                let responses: Vec<Scalar> = alternative.witnesses.iter().map(|_| Scalar::random(rng)).collect();
+                commitments.extend(alternative.recompute(&responses, &challenge));
+                simulated.push(Proof {
+                    challenge,
+                    responses,
+                    alternatives: Vec::new(),
+                });
+            }
+        }
+
+        self.append_to(&mut transcript);
+        for alternative in alternatives {
+            alternative.append_to(&mut transcript);
+        }
+        for commitment in commitments.iter() {
+            transcript.append_point(b"commitment", commitment);
+        }
+        let challenge = transcript.challenge();
+
+        if let Some(alternative) = alternatives.get(real) {
+            let real_challenge = simulated
+                .iter()
+                .enumerate()
+                .filter(|(index, _)| *index != real)
+                .fold(challenge, |remaining, (_, proof)| remaining - proof.challenge);
+
+            simulated[real] = Proof {
+                challenge: real_challenge,
+                responses: respond(&real_nonces, &alternative.witnesses, &real_challenge),
+                alternatives: Vec::new(),
+            };
+        }
+
+        Proof {
+            challenge,
+            responses: respond(&nonces, &self.witnesses, &challenge),
+            alternatives: simulated,
+        }
+    }
+
+    pub(crate) fn verify_or(&self, alternatives: &[Statement], mut transcript: Transcript, proof: &Proof) -> bool {
+        if proof.responses.len() != self.witnesses.len()
+            || proof.alternatives.len() != alternatives.len()
+            || proof
+                .alternatives
+                .iter()
+                .zip(alternatives.iter())
+                .any(|(branch, alternative)| {
+                    branch.responses.len() != alternative.witnesses.len() || !branch.alternatives.is_empty()
+                })
+        {
+            return false;
+        }
+
+        if !alternatives.is_empty()
+            && proof.alternatives.iter().map(|branch| branch.challenge).sum::<Scalar>() != proof.challenge
+        {
+            return false;
+        }
+
+        let mut commitments = self.recompute(&proof.responses, &proof.challenge);
+        for (branch, alternative) in proof.alternatives.iter().zip(alternatives.iter()) {
+            commitments.extend(alternative.recompute(&branch.responses, &branch.challenge));
+        }
+
+        self.append_to(&mut transcript);
+        for alternative in alternatives {
+            alternative.append_to(&mut transcript);
+        }
+        for commitment in commitments.iter() {
+            transcript.append_point(b"commitment", commitment);
+        }
+
+        transcript.challenge() == proof.challenge
+    }
+}
+
+fn respond(nonces: &[Scalar], witnesses: &[Scalar], challenge: &Scalar) -> Vec<Scalar> {
+    nonces
+        .iter()
+        .zip(witnesses.iter())
+        .map(|(nonce, witness)| nonce + challenge * witness)
+        .collect()
+}
+
+// Proof is a non-interactive proof for a Statement in compact form: the
+// challenge and one response per witness, plus a challenge and responses for
+// each alternative of a prove_or
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Proof {
+    pub(crate) challenge: Scalar,
+    pub(crate) responses: Vec<Scalar>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) alternatives: Vec<Proof>,
+}
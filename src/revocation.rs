@@ -0,0 +1,96 @@
+use crate::errors::VerifyingError;
+use crate::proof::{Proof, Statement, Transcript};
+
+use curve25519_dalek::ristretto::RistrettoPoint;
+use curve25519_dalek::scalar::Scalar;
+
+use rand_core::{CryptoRng, RngCore};
+
+use serde::{Deserialize, Serialize};
+
+// Revocation works on a revocation handle: a random attribute the issuer
+// assigns at issuance (see IssuanceRequest::with_issuer_attributes) and
+// records with the user it was issued to. To revoke, the issuer adds the
+// handle to the next RevocationList. Presentations under a policy with
+// revocation prove that their hidden handle is none of the listed ones.
+//
+// Lists are signed with a RevocationKey, which must be separate from the
+// issuer's SigningKey: a blind signer answers arbitrary challenges, so its key
+// can't safely sign anything else.
+#[derive(Clone)]
+pub struct RevocationKey {
+    pub(crate) scalar: Scalar,
+}
+
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct RevocationVerifyingKey {
+    pub(crate) point: RistrettoPoint,
+}
+
+// RevocationList is the signed list of revoked handles for one epoch. Epochs
+// must increase with every list, so that a verifier can refuse stale lists.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct RevocationList {
+    pub epoch: u64,
+    pub revoked: Vec<Scalar>,
+    pub(crate) proof: Proof,
+}
+
+impl RevocationKey {
+    pub fn random<R: RngCore + CryptoRng>(rng: &mut R) -> RevocationKey {
+        RevocationKey {
+            scalar: Scalar::random(rng),
+        }
+    }
+
+    pub fn verifying_key(&self) -> RevocationVerifyingKey {
+        RevocationVerifyingKey {
+            point: RistrettoPoint::mul_base(&self.scalar),
+        }
+    }
+
+    // sign signs the list with a Schnorr signature, i.e. a proof of knowledge
+    // of the key over a transcript of the list
+    pub fn sign<R: RngCore + CryptoRng>(&self, rng: &mut R, epoch: u64, revoked: Vec<Scalar>) -> RevocationList {
+        let key = self.verifying_key();
+        let mut list = RevocationList {
+            epoch,
+            revoked,
+            proof: Proof::default(),
+        };
+
+        list.proof = Statement::discrete_log(key.point, self.scalar).prove(rng, list.transcript(&key));
+        list
+    }
+}
+
+impl RevocationList {
+    pub fn verify(&self, key: &RevocationVerifyingKey) -> Result<(), VerifyingError> {
+        if Statement::discrete_log(key.point, Scalar::ZERO).verify(self.transcript(key), &self.proof) {
+            Ok(())
+        } else {
+            Err(VerifyingError::InvalidProof)
+        }
+    }
+
+    pub fn contains(&self, handle: &Scalar) -> bool {
+        self.revoked.contains(handle)
+    }
+
+    // excludes checks that tag = handle * base for none of the revoked handles.
+    // A presentation proves that tag = handle * base for its own handle, with a
+    // fresh random base so that tags can't be linked across presentations.
+    pub(crate) fn excludes(&self, base: &RistrettoPoint, tag: &RistrettoPoint) -> bool {
+        self.revoked.iter().all(|handle| base * handle != *tag)
+    }
+
+    fn transcript(&self, key: &RevocationVerifyingKey) -> Transcript {
+        let mut transcript = Transcript::new(b"acl revocation list");
+        transcript.append_point(b"key", &key.point);
+        transcript.append_message(b"epoch", &self.epoch.to_le_bytes());
+        for handle in self.revoked.iter() {
+            transcript.append_message(b"handle", handle.as_bytes());
+        }
+        transcript
+    }
+}
@@ -1,7 +1,10 @@
-use curve25519_dalek::ristretto::RistrettoPoint;
+use curve25519_dalek::ristretto::{CompressedRistretto, RistrettoPoint};
 use curve25519_dalek::scalar::Scalar;
 use serde::{Serialize,Deserialize};
 
+use crate::errors::VerifyingError;
+use crate::verifying::VerifyingKey;
+
 #[derive(Copy, Clone, Eq,Debug, PartialEq, Serialize, Deserialize)]
 pub struct Signature {
     pub xi: RistrettoPoint,
@@ -27,3 +30,51 @@ impl Signature {
         .concat()
     }
 }
+
+// BatchableSignature is a signature together with the points alpha, beta1,
+// beta2 and eta that its challenge hashes. A plain signature only lets the
+// verifier recompute those points, one signature at a time, as
+// VerifyingKey::verify_batch does; with them given,
+// VerifyingKey::verify_batchable checks the hashes directly and folds all the
+// verification equations into one random linear combination. See
+// VerifyingKey::batchable.
+#[derive(Copy, Clone, Eq, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BatchableSignature {
+    pub signature: Signature,
+    pub alpha: CompressedRistretto,
+    pub beta1: CompressedRistretto,
+    pub beta2: CompressedRistretto,
+    pub eta: CompressedRistretto,
+}
+
+impl BatchableSignature {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        [
+            self.signature.to_bytes(),
+            self.alpha.to_bytes().to_vec(),
+            self.beta1.to_bytes().to_vec(),
+            self.beta2.to_bytes().to_vec(),
+            self.eta.to_bytes().to_vec(),
+        ]
+        .concat()
+    }
+}
+
+// Credential is what compute_signature returns: the signature, the blinded
+// commitment and hashed message it verifies under, and the user's blinding
+// secrets gamma and rnd, which are needed to prove anything about the
+// attributes later. Token adds the opening of the commitment.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Credential {
+    pub signature: Signature,
+    pub blinded_commitment: RistrettoPoint,
+    pub hashed_message: Vec<u8>,
+    pub gamma: Scalar,
+    pub rnd: Scalar,
+}
+
+impl Credential {
+    pub fn verify(&self, key: &VerifyingKey) -> Result<(), VerifyingError> {
+        key.verify_prehashed(&self.hashed_message, &self.blinded_commitment, &self.signature)
+    }
+}
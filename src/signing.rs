@@ -8,31 +8,35 @@ use rand_core::{CryptoRng, OsRng, RngCore};
 use std::fmt::Debug;
 
 use crate::{
-    constants::{gen_h, gen_z, SECRET_KEY_LENGTH},
-    errors::SigningError,
+    attributes::Schema,
+    constants::{gen_h_table, gen_z, MAX_BATCH_SIZE, SECRET_KEY_LENGTH},
+    errors::{SigningError, VerifyingError},
+    issuance::{ExpiryWindow, IssuanceRequest, ReissuanceRequest},
+    spent::SpentTokenStore,
+    verifying::VerifyingKey,
 };
 
 pub type SecretKey = [u8; SECRET_KEY_LENGTH];
 
-// There's not *really* a reason to not just put the secret key as a scalar in
-// this struct, except that in the future we might want to do something similar
-// to EdDSA, i.e., using part of the secret key as a salt to a hash function.
+// Like EdDSA, the first half of SHA512(secret key) becomes the signing scalar
+// and the second half is kept as a salt for deriving hedged nonces.
 #[derive(Copy,Clone,Debug)]
 pub struct SigningKey {
     pub(crate) scalar: Scalar,
+    pub(crate) nonce_seed: [u8; 32],
 }
 
 #[derive(Debug)]
 pub struct SignerState {
-    d: Scalar,
-    s1: Scalar,
-    s2: Scalar,
-    u: Scalar,
-    rnd: Scalar,
+    pub(crate) d: Scalar,
+    pub(crate) s1: Scalar,
+    pub(crate) s2: Scalar,
+    pub(crate) u: Scalar,
+    pub(crate) rnd: Scalar,
 }
 
 impl SignerState {
-    fn random<R: RngCore + CryptoRng>(rng: &mut R) -> Self {
+    pub(crate) fn random<R: RngCore + CryptoRng>(rng: &mut R) -> Self {
         SignerState {
             d: Scalar::random(rng),
             s1: Scalar::random(rng),
@@ -41,6 +45,46 @@ impl SignerState {
             rnd: Scalar::random(rng),
         }
     }
+
+    // hedged derives every nonce from the key's nonce seed, the commitment, the
+    // session id and fresh randomness, so that a broken RNG alone can't make
+    // two sessions share u and d (which would reveal the signing key), and a
+    // repeated session id alone doesn't either as long as the RNG works
+    fn hedged<R: RngCore + CryptoRng>(
+        nonce_seed: &[u8; 32],
+        commitment: &RistrettoPoint,
+        session_id: &[u8],
+        rng: &mut R,
+    ) -> Self {
+        let mut entropy = [0u8; 32];
+        rng.fill_bytes(&mut entropy);
+
+        let derive = |label: &[u8]| {
+            let mut hash = Sha512::new();
+            hash.update(b"acl hedged signer nonce");
+            hash.update(label);
+            hash.update(nonce_seed);
+            hash.update(commitment.compress().as_bytes());
+            hash.update((session_id.len() as u64).to_le_bytes());
+            hash.update(session_id);
+            hash.update(entropy);
+            Scalar::from_hash(hash)
+        };
+
+        SignerState {
+            d: derive(b"d"),
+            s1: derive(b"s1"),
+            s2: derive(b"s2"),
+            u: derive(b"u"),
+            rnd: derive(b"rnd"),
+        }
+    }
+}
+
+// BatchSignerState holds one SignerState per token of a batch, in order
+#[derive(Debug)]
+pub struct BatchSignerState {
+    states: Vec<SignerState>,
 }
 
 #[derive(Debug)]
@@ -52,7 +96,21 @@ pub(crate) struct PrepareMessage {
 }
 
 impl PrepareMessage {
-    fn to_bytes(&self) -> Vec<u8> {
+    // new computes the prepare message for a state whose nonce u is behind
+    // a = u*G
+    pub(crate) fn new(state: &SignerState, a: RistrettoPoint, commitment: &RistrettoPoint) -> PrepareMessage {
+        let z1 = RistrettoPoint::mul_base(&state.rnd) + commitment;
+        let z2 = gen_z() - z1;
+
+        PrepareMessage {
+            a,
+            b1: RistrettoPoint::mul_base(&state.s1) + z1 * state.d,
+            b2: gen_h_table() * &state.s2 + z2 * state.d,
+            rnd: state.rnd,
+        }
+    }
+
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
         [
             self.a.compress().to_bytes(),
             self.b1.compress().to_bytes(),
@@ -72,7 +130,7 @@ pub(crate) struct PreSignature {
 }
 
 impl PreSignature {
-    fn to_bytes(&self) -> Vec<u8> {
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
         [
             self.c.to_bytes(),
             self.d.to_bytes(),
@@ -84,6 +142,30 @@ impl PreSignature {
     }
 }
 
+// A batch travels as one framed message: the number of records as a
+// little-endian u32, followed by the fixed-size records themselves.
+pub(crate) fn frame(records: &[Vec<u8>]) -> Vec<u8> {
+    let mut framed = Vec::from((records.len() as u32).to_le_bytes());
+    for record in records {
+        framed.extend_from_slice(record);
+    }
+    framed
+}
+
+// unframe splits a framed message into records of record_len bytes, or
+// returns None if the framing is inconsistent or the batch is empty or larger
+// than MAX_BATCH_SIZE
+pub(crate) fn unframe(framed: &[u8], record_len: usize) -> Option<Vec<&[u8]>> {
+    let count = u32::from_le_bytes(framed.get(0..4)?.try_into().ok()?) as usize;
+    let records = &framed[4..];
+
+    if count == 0 || count > MAX_BATCH_SIZE || records.len() != count * record_len {
+        return None;
+    }
+
+    Some(records.chunks_exact(record_len).collect())
+}
+
 /*pub trait SignatureProvider {
     fn prepare(&self, commitment: &RistrettoPoint, aux: &[u8]) -> Result<[u8; 32*4], SigningError>;
     fn compute_presignature(&self, challenge_bytes: &[u8]) -> Result<&[u8], String>;
@@ -98,53 +180,399 @@ impl SigningKey {
         let mut scalar_bytes: [u8; 32] = [0u8; 32];
         scalar_bytes.copy_from_slice(&digest.as_slice()[00..32]);
 
+        let mut nonce_seed: [u8; 32] = [0u8; 32];
+        nonce_seed.copy_from_slice(&digest.as_slice()[32..64]);
+
         Self {
             scalar: Scalar::from_bytes_mod_order(clamp_integer(scalar_bytes)),
+            nonce_seed,
         }
     }
 
     // prepare generates the first message in the ACL protocol, which the user
-    // will use to generate a response
+    // will use to generate a response. It signs commitment unchecked; use
+    // prepare_verified unless the commitment's opening is known some other way.
     pub fn prepare(
         &self,
         commitment: &RistrettoPoint,
     ) -> Result<(SignerState, Vec<u8>), SigningError> {
-        let state = SignerState::random(&mut OsRng);
+        self.prepare_with_rng(&mut OsRng, commitment)
+    }
 
-        let z1 = RistrettoPoint::mul_base(&state.rnd) + commitment;
-        let z2 = gen_z() - z1;
+    // prepare_verified is prepare for an IssuanceRequest: it refuses to sign
+    // unless the request proves that its commitment opens under the schema
+    pub fn prepare_verified(
+        &self,
+        schema: &Schema,
+        request: &IssuanceRequest,
+    ) -> Result<(SignerState, Vec<u8>), SigningError> {
+        self.prepare_with_attributes(schema, request, &[])
+    }
 
-        let msg = PrepareMessage {
-            a: RistrettoPoint::mul_base(&state.u),
-            b1: RistrettoPoint::mul_base(&state.s1) + z1 * state.d,
-            b2: gen_h() * state.s2 + z2 * state.d,
-            rnd: state.rnd.clone(),
-        };
+    // prepare_with_attributes is prepare_verified for requests that leave some
+    // attributes to the issuer: issuer_attributes are (schema index, value) for
+    // exactly the request's issuer_assigned indices, and are added to the
+    // user's commitment before signing. The user learns the values (they need
+    // them to use the credential) but the issuer never learns the hidden ones.
+    pub fn prepare_with_attributes(
+        &self,
+        schema: &Schema,
+        request: &IssuanceRequest,
+        issuer_attributes: &[(usize, Scalar)],
+    ) -> Result<(SignerState, Vec<u8>), SigningError> {
+        request.verify(schema)?;
+        self.prepare(&request.certified_commitment(schema, issuer_attributes)?)
+    }
+
+    // prepare_reissued is prepare_with_attributes for a ReissuanceRequest,
+    // with the issuer's values for exactly the refreshed indices. The old
+    // signature is recorded in spent, and a credential that was refreshed
+    // before is refused with VerifyingError::Replayed.
+    pub fn prepare_reissued<S: SpentTokenStore + ?Sized>(
+        &self,
+        schema: &Schema,
+        request: &ReissuanceRequest,
+        expiry: Option<&ExpiryWindow>,
+        issuer_attributes: &[(usize, Scalar)],
+        spent: &S,
+    ) -> Result<(SignerState, Vec<u8>), SigningError> {
+        request.verify(&VerifyingKey::from(self), schema, expiry)?;
+        let commitment = request.certified_commitment(schema, issuer_attributes)?;
+
+        let fresh = spent
+            .insert(&request.signature.xi.compress().to_bytes())
+            .map_err(|err| SigningError::Credential { err: err.into() })?;
+        if !fresh {
+            return Err(SigningError::Credential {
+                err: VerifyingError::Replayed,
+            });
+        }
+
+        self.prepare(&commitment)
+    }
+
+    // prepare_with_rng is prepare with a caller-supplied source of randomness
+    // for the signer's nonces, e.g. a seeded RNG for known-answer tests
+    pub fn prepare_with_rng<R: RngCore + CryptoRng>(
+        &self,
+        rng: &mut R,
+        commitment: &RistrettoPoint,
+    ) -> Result<(SignerState, Vec<u8>), SigningError> {
+        self.prepare_with_state(SignerState::random(rng), commitment)
+    }
+
+    // prepare_hedged is prepare in hedged-nonce mode: the signer's nonces are
+    // derived from the secret key, the commitment, session_id and fresh
+    // randomness instead of being drawn from the RNG directly. session_id
+    // should be unique per issuance session, e.g. a counter or request id.
+    pub fn prepare_hedged(
+        &self,
+        commitment: &RistrettoPoint,
+        session_id: &[u8],
+    ) -> Result<(SignerState, Vec<u8>), SigningError> {
+        self.prepare_hedged_with_rng(&mut OsRng, commitment, session_id)
+    }
+
+    pub fn prepare_hedged_with_rng<R: RngCore + CryptoRng>(
+        &self,
+        rng: &mut R,
+        commitment: &RistrettoPoint,
+        session_id: &[u8],
+    ) -> Result<(SignerState, Vec<u8>), SigningError> {
+        self.prepare_with_state(
+            SignerState::hedged(&self.nonce_seed, commitment, session_id, rng),
+            commitment,
+        )
+    }
+
+    fn prepare_with_state(
+        &self,
+        state: SignerState,
+        commitment: &RistrettoPoint,
+    ) -> Result<(SignerState, Vec<u8>), SigningError> {
+        let msg = PrepareMessage::new(&state, RistrettoPoint::mul_base(&state.u), commitment);
 
         Ok((state, msg.to_bytes()))
     }
 
     // compute_presignature generates a "presignature" from a challenge, which
-    // the user will be able to obtain the final signature from
+    // the user will be able to obtain the final signature from. It takes the
+    // state by value: a state must never answer two different challenges,
+    // since that reveals the signing key.
     pub fn compute_presignature(
         &self,
-        state: &SignerState,
+        state: SignerState,
         challenge_bytes: &[u8],
-    ) -> Result<Vec<u8>, String> {
-        let e = Scalar::from_canonical_bytes(challenge_bytes.try_into().expect("todo"))
+    ) -> Result<Vec<u8>, SigningError> {
+        let e = Scalar::from_canonical_bytes(challenge_bytes.try_into().map_err(|_| SigningError::ScalarFormat)?)
             .into_option()
-            .ok_or("unable to parse challenge as scalar")?;
+            .ok_or(SigningError::ScalarFormat)?;
 
+        Ok(self.presign(state, &e).to_bytes())
+    }
+
+    fn presign(&self, state: SignerState, e: &Scalar) -> PreSignature {
         let c = e - state.d;
         let r = state.u - c * self.scalar;
 
-        Ok((&PreSignature {
-            c: c,
-            d: state.d.clone(),
-            r: r,
-            s1: state.s1.clone(),
-            s2: state.s2.clone(),
-        })
-            .to_bytes())
+        PreSignature {
+            c,
+            d: state.d,
+            r,
+            s1: state.s1,
+            s2: state.s2,
+        }
+    }
+
+    // prepare_batch runs prepare for every commitment at once and frames the
+    // prepare messages into a single message. Each token gets independent
+    // nonces, and the user blinds each one independently, so tokens from one
+    // batch are as unlinkable as tokens from separate runs. See MAX_BATCH_SIZE
+    // on how batches relate to concurrent sessions. Like prepare, it signs the
+    // commitments unchecked; see prepare_batch_with_attributes.
+    pub fn prepare_batch(
+        &self,
+        commitments: &[RistrettoPoint],
+    ) -> Result<(BatchSignerState, Vec<u8>), SigningError> {
+        self.prepare_batch_with_rng(&mut OsRng, commitments)
+    }
+
+    pub fn prepare_batch_with_rng<R: RngCore + CryptoRng>(
+        &self,
+        rng: &mut R,
+        commitments: &[RistrettoPoint],
+    ) -> Result<(BatchSignerState, Vec<u8>), SigningError> {
+        if commitments.is_empty() || commitments.len() > MAX_BATCH_SIZE {
+            return Err(SigningError::BatchSize);
+        }
+
+        let (states, messages) = commitments
+            .iter()
+            .map(|commitment| self.prepare_with_rng(rng, commitment))
+            .collect::<Result<(Vec<SignerState>, Vec<Vec<u8>>), SigningError>>()?;
+
+        Ok((BatchSignerState { states }, frame(&messages)))
+    }
+
+    // prepare_batch_with_attributes is prepare_with_attributes for a batch:
+    // every request must verify, and issuer_attributes holds the issuer's
+    // values for each request, in the same order
+    pub fn prepare_batch_with_attributes(
+        &self,
+        schema: &Schema,
+        requests: &[IssuanceRequest],
+        issuer_attributes: &[&[(usize, Scalar)]],
+    ) -> Result<(BatchSignerState, Vec<u8>), SigningError> {
+        if requests.len() != issuer_attributes.len() {
+            return Err(SigningError::BatchSize);
+        }
+
+        let commitments = requests
+            .iter()
+            .zip(issuer_attributes)
+            .map(|(request, issuer_attributes)| {
+                request.verify(schema)?;
+                request.certified_commitment(schema, issuer_attributes)
+            })
+            .collect::<Result<Vec<RistrettoPoint>, SigningError>>()?;
+
+        self.prepare_batch(&commitments)
+    }
+
+    // compute_presignature_batch answers a framed batch of challenges, one per
+    // token in the order of prepare_batch. Like compute_presignature, it takes
+    // the state by value.
+    pub fn compute_presignature_batch(
+        &self,
+        state: BatchSignerState,
+        challenges: &[u8],
+    ) -> Result<Vec<u8>, SigningError> {
+        let challenges = unframe(challenges, 32).ok_or(SigningError::BatchSize)?;
+        if challenges.len() != state.states.len() {
+            return Err(SigningError::BatchSize);
+        }
+
+        let presignatures = state
+            .states
+            .into_iter()
+            .zip(challenges)
+            .map(|(state, challenge)| {
+                let e = Scalar::from_canonical_bytes(challenge.try_into().map_err(|_| SigningError::ScalarFormat)?)
+                    .into_option()
+                    .ok_or(SigningError::ScalarFormat)?;
+                Ok(self.presign(state, &e).to_bytes())
+            })
+            .collect::<Result<Vec<Vec<u8>>, SigningError>>()?;
+
+        Ok(frame(&presignatures))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::errors::UserError;
+    use crate::user::UserParameters;
+    use crate::verifying::VerifyingKey;
+    use rand_chacha::ChaCha20Rng;
+    use rand_core::SeedableRng;
+
+    #[test]
+    fn prepare_with_seeded_rng_is_deterministic() {
+        let signing_key = SigningKey::from_bytes(&[7u8; 32]);
+        let commitment = RistrettoPoint::mul_base(&Scalar::from(42u8));
+
+        let (_, first) = signing_key
+            .prepare_with_rng(&mut ChaCha20Rng::seed_from_u64(1), &commitment)
+            .unwrap();
+        let (_, second) = signing_key
+            .prepare_with_rng(&mut ChaCha20Rng::seed_from_u64(1), &commitment)
+            .unwrap();
+        let (_, other) = signing_key
+            .prepare_with_rng(&mut ChaCha20Rng::seed_from_u64(2), &commitment)
+            .unwrap();
+
+        assert_eq!(first, second);
+        assert_ne!(first, other);
+    }
+
+    #[test]
+    fn seeded_issuance_produces_the_same_signature() {
+        let signing_key = SigningKey::from_bytes(&[7u8; 32]);
+        let user_params = UserParameters {
+            key: VerifyingKey::from(&signing_key),
+        };
+        let commitment = RistrettoPoint::mul_base(&Scalar::from(42u8));
+
+        let issue = || {
+            let mut signer_rng = ChaCha20Rng::seed_from_u64(1);
+            let mut user_rng = ChaCha20Rng::seed_from_u64(2);
+
+            let (ss, prepare_message) = signing_key.prepare_with_rng(&mut signer_rng, &commitment).unwrap();
+            let (us, challenge) = user_params
+                .compute_challenge(&mut user_rng, &commitment, &[0u8; 64], &prepare_message)
+                .unwrap();
+            let presignature = signing_key.compute_presignature(ss, &challenge).unwrap();
+            user_params.compute_signature(&us, &presignature).unwrap()
+        };
+
+        let credential = issue();
+        assert_eq!(credential.signature, issue().signature);
+        assert!(credential.verify(&user_params.key).is_ok());
+    }
+
+    #[test]
+    fn batch_issuance() {
+        let signing_key = SigningKey::from_bytes(&[7u8; 32]);
+        let user_params = UserParameters {
+            key: VerifyingKey::from(&signing_key),
+        };
+        let commitments: Vec<RistrettoPoint> = (0u8..3).map(|i| RistrettoPoint::mul_base(&Scalar::from(i + 1))).collect();
+        let requests: Vec<(&RistrettoPoint, &[u8])> = commitments.iter().map(|c| (c, &[0u8; 64][..])).collect();
+
+        let (ss, prepare_message) = signing_key.prepare_batch(&commitments).unwrap();
+        let (us, challenges) = user_params
+            .compute_challenge_batch(&mut OsRng, &requests, &prepare_message)
+            .unwrap();
+
+        let presignatures = signing_key.compute_presignature_batch(ss, &challenges).unwrap();
+        let credentials = user_params.compute_signature_batch(&us, &presignatures).unwrap();
+
+        assert_eq!(credentials.len(), 3);
+        for credential in credentials.iter() {
+            assert!(credential.verify(&user_params.key).is_ok());
+        }
+        assert_ne!(credentials[0].signature.xi, credentials[1].signature.xi);
+
+        assert_eq!(
+            user_params
+                .compute_challenge_batch(&mut OsRng, &requests[..2], &prepare_message)
+                .unwrap_err(),
+            UserError::BatchSize
+        );
+        assert_eq!(
+            user_params.compute_signature_batch(&us[..2], &presignatures).unwrap_err(),
+            UserError::BatchSize
+        );
+    }
+
+    #[test]
+    fn batch_size_is_limited() {
+        let signing_key = SigningKey::from_bytes(&[7u8; 32]);
+        let commitments = vec![RistrettoPoint::mul_base(&Scalar::ONE); MAX_BATCH_SIZE + 1];
+
+        assert_eq!(signing_key.prepare_batch(&[]).unwrap_err(), SigningError::BatchSize);
+        assert_eq!(signing_key.prepare_batch(&commitments).unwrap_err(), SigningError::BatchSize);
+    }
+
+    #[test]
+    fn hedged_nonces_differ_across_sessions_with_a_stuck_rng() {
+        let signing_key = SigningKey::from_bytes(&[7u8; 32]);
+        let commitment = RistrettoPoint::mul_base(&Scalar::from(42u8));
+        let other_commitment = RistrettoPoint::mul_base(&Scalar::from(43u8));
+
+        let mut seen = std::collections::HashSet::new();
+        for session in 0u32..64 {
+            for commitment in [&commitment, &other_commitment] {
+                let (state, _) = signing_key
+                    .prepare_hedged_with_rng(&mut ChaCha20Rng::seed_from_u64(1), commitment, &session.to_le_bytes())
+                    .unwrap();
+                assert!(seen.insert(state.u.to_bytes()));
+                assert!(seen.insert(state.d.to_bytes()));
+            }
+        }
+
+        // with the same key, commitment and session id only the RNG separates
+        // the two runs
+        let (first, _) = signing_key
+            .prepare_hedged_with_rng(&mut ChaCha20Rng::seed_from_u64(1), &commitment, b"session")
+            .unwrap();
+        let (second, _) = signing_key
+            .prepare_hedged_with_rng(&mut ChaCha20Rng::seed_from_u64(2), &commitment, b"session")
+            .unwrap();
+        assert_ne!(first.u, second.u);
+
+        // and different keys never share nonces
+        let other_key = SigningKey::from_bytes(&[8u8; 32]);
+        let (other, _) = other_key
+            .prepare_hedged_with_rng(&mut ChaCha20Rng::seed_from_u64(1), &commitment, b"session")
+            .unwrap();
+        assert_ne!(first.u, other.u);
+    }
+
+    #[test]
+    fn hedged_issuance_verifies() {
+        let signing_key = SigningKey::from_bytes(&[7u8; 32]);
+        let user_params = UserParameters {
+            key: VerifyingKey::from(&signing_key),
+        };
+        let commitment = RistrettoPoint::mul_base(&Scalar::from(42u8));
+
+        let (ss, prepare_message) = signing_key.prepare_hedged(&commitment, b"session 1").unwrap();
+        let (us, challenge) = user_params
+            .compute_challenge(&mut OsRng, &commitment, &[0u8; 64], &prepare_message)
+            .unwrap();
+        let presignature = signing_key.compute_presignature(ss, &challenge).unwrap();
+        let credential = user_params.compute_signature(&us, &presignature).unwrap();
+
+        assert!(credential.verify(&user_params.key).is_ok());
+    }
+
+    #[test]
+    fn malformed_challenges_are_rejected() {
+        let signing_key = SigningKey::from_bytes(&[7u8; 32]);
+        let commitment = RistrettoPoint::mul_base(&Scalar::from(42u8));
+
+        let (ss, _) = signing_key.prepare(&commitment).unwrap();
+        assert_eq!(
+            signing_key.compute_presignature(ss, &[0u8; 31]).unwrap_err(),
+            SigningError::ScalarFormat
+        );
+
+        let (ss, _) = signing_key.prepare(&commitment).unwrap();
+        assert_eq!(
+            signing_key.compute_presignature(ss, &[0xffu8; 32]).unwrap_err(),
+            SigningError::ScalarFormat
+        );
     }
 }
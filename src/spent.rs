@@ -0,0 +1,374 @@
+use crate::attributes::Schema;
+use crate::errors::{StoreError, VerifyingError};
+use crate::presentation::{Presentation, PresentationPolicy};
+use crate::signature::Signature;
+use crate::token::SpendProof;
+use crate::verifying::{PrecomputedVerifyingKey, VerifyingKey};
+
+use curve25519_dalek::ristretto::RistrettoPoint;
+
+use std::collections::HashSet;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+// SpentTokenStore remembers which one-show credentials have been seen, keyed
+// by a 32-byte id (the compressed xi of the signature, or a serial number)
+pub trait SpentTokenStore {
+    // insert records id as spent and returns whether it was new. It must be
+    // atomic: of several concurrent inserts of the same id, exactly one
+    // returns Ok(true).
+    fn insert(&self, id: &[u8; 32]) -> Result<bool, StoreError>;
+
+    fn contains(&self, id: &[u8; 32]) -> Result<bool, StoreError>;
+}
+
+// a store can be shared, e.g. by verifiers for several keys
+impl<T: SpentTokenStore + ?Sized> SpentTokenStore for Arc<T> {
+    fn insert(&self, id: &[u8; 32]) -> Result<bool, StoreError> {
+        (**self).insert(id)
+    }
+
+    fn contains(&self, id: &[u8; 32]) -> Result<bool, StoreError> {
+        (**self).contains(id)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct MemorySpentTokenStore {
+    spent: Mutex<HashSet<[u8; 32]>>,
+}
+
+impl MemorySpentTokenStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl SpentTokenStore for MemorySpentTokenStore {
+    fn insert(&self, id: &[u8; 32]) -> Result<bool, StoreError> {
+        Ok(self.spent.lock().map_err(|_| StoreError::Poisoned)?.insert(*id))
+    }
+
+    fn contains(&self, id: &[u8; 32]) -> Result<bool, StoreError> {
+        Ok(self.spent.lock().map_err(|_| StoreError::Poisoned)?.contains(id))
+    }
+}
+
+// FileSpentTokenStore is an append-only log of 32-byte ids, synced to disk
+// before insert returns. The whole log is loaded into memory on open. Only one
+// process may have a given file open at a time.
+//
+// The store keeps the length of the log up to the last complete id. A failed
+// append is cut back to it, and if that fails too, the next insert cuts it back
+// before appending, or fails. An id after a fragment would be misaligned, and
+// so would every id after it.
+#[derive(Debug)]
+pub struct FileSpentTokenStore {
+    inner: Mutex<(File, HashSet<[u8; 32]>, u64)>,
+}
+
+impl FileSpentTokenStore {
+    // open syncs the log's directory too, so that a newly created log can't
+    // vanish in a power loss along with the ids later synced into it
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, StoreError> {
+        let mut file = OpenOptions::new().read(true).append(true).create(true).open(path.as_ref())?;
+
+        // only unix lets a directory be opened and synced like a file
+        #[cfg(unix)]
+        {
+            let directory = match path.as_ref().parent() {
+                Some(parent) if !parent.as_os_str().is_empty() => parent,
+                _ => Path::new("."),
+            };
+            File::open(directory)?.sync_all()?;
+        }
+
+        let mut log = Vec::new();
+        file.read_to_end(&mut log)?;
+
+        // a crash in the middle of an append leaves a partial id at the end,
+        // which was never reported as inserted and can be dropped
+        let complete = log.len() - log.len() % 32;
+        if complete != log.len() {
+            file.set_len(complete as u64)?;
+        }
+
+        let spent = log[..complete]
+            .chunks_exact(32)
+            .map(|id| id.try_into().expect("chunk is 32 bytes"))
+            .collect();
+
+        Ok(FileSpentTokenStore {
+            inner: Mutex::new((file, spent, complete as u64)),
+        })
+    }
+}
+
+impl SpentTokenStore for FileSpentTokenStore {
+    fn insert(&self, id: &[u8; 32]) -> Result<bool, StoreError> {
+        let mut inner = self.inner.lock().map_err(|_| StoreError::Poisoned)?;
+        let (file, spent, len) = &mut *inner;
+
+        if spent.contains(id) {
+            return Ok(false);
+        }
+
+        if file.metadata()?.len() != *len {
+            file.set_len(*len)?;
+        }
+
+        if let Err(err) = file.write_all(id).and_then(|_| file.sync_data()) {
+            let _ = file.set_len(*len);
+            return Err(err.into());
+        }
+        spent.insert(*id);
+        *len += 32;
+        Ok(true)
+    }
+
+    fn contains(&self, id: &[u8; 32]) -> Result<bool, StoreError> {
+        Ok(self.inner.lock().map_err(|_| StoreError::Poisoned)?.1.contains(id))
+    }
+}
+
+// Verifier checks one-show credentials under a single key and records each
+// accepted signature in a SpentTokenStore, rejecting any later presentation of
+// the same signature. Rate-limit tags go into the same store.
+pub struct Verifier<S: SpentTokenStore> {
+    key: PrecomputedVerifyingKey,
+    store: S,
+}
+
+impl<S: SpentTokenStore> Verifier<S> {
+    pub fn new(key: &VerifyingKey, store: S) -> Self {
+        Verifier {
+            key: PrecomputedVerifyingKey::from(key),
+            store,
+        }
+    }
+
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    pub fn verifying_key(&self) -> &VerifyingKey {
+        self.key.verifying_key()
+    }
+
+    pub fn verify_prehashed(
+        &self,
+        hashed_message: &[u8],
+        commitment: &RistrettoPoint,
+        sig: &Signature,
+    ) -> Result<(), VerifyingError> {
+        self.key.verify_prehashed(hashed_message, commitment, sig)?;
+        self.spend(sig)
+    }
+
+    pub fn verify_spend(
+        &self,
+        spend: &SpendProof,
+        schema: &Schema,
+        id_index: usize,
+        context: &[u8],
+    ) -> Result<(), VerifyingError> {
+        spend.verify(self.key.verifying_key(), schema, id_index, context)?;
+        self.spend(&spend.signature)
+    }
+
+    // verify_presentation checks a presentation and records its signature as
+    // spent. If the policy has a rate limit, it also records the presentation's
+    // tag, and rejects the presentation if the tag was seen before. The tag
+    // goes in first, so that a presentation over the limit doesn't use up its
+    // token.
+    pub fn verify_presentation(
+        &self,
+        presentation: &Presentation,
+        schema: &Schema,
+        policy: &PresentationPolicy,
+        context: &[u8],
+    ) -> Result<(), VerifyingError> {
+        presentation.verify(self.key.verifying_key(), schema, policy, context)?;
+
+        if let Some(rate_limit) = presentation.rate_limit {
+            // a replay would otherwise be reported as over the limit, since
+            // its tag was recorded along with its signature
+            if self.store.contains(&presentation.signature.xi.compress().to_bytes())? {
+                return Err(VerifyingError::Replayed);
+            }
+            if !self.store.insert(&rate_limit.tag.compress().to_bytes())? {
+                return Err(VerifyingError::RateLimited);
+            }
+        }
+
+        self.spend(&presentation.signature)
+    }
+
+    // spend must only run after verification, so that invalid signatures
+    // can't fill up the store
+    fn spend(&self, sig: &Signature) -> Result<(), VerifyingError> {
+        if self.store.insert(&sig.xi.compress().to_bytes())? {
+            Ok(())
+        } else {
+            Err(VerifyingError::Replayed)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::errors::UserError;
+    use crate::presentation::RateLimit;
+    use crate::signing::SigningKey;
+    use crate::token::tests::{issue, schema};
+    use curve25519_dalek::scalar::Scalar;
+    use rand_core::OsRng;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    #[test]
+    fn file_store_persists_and_drops_partial_ids() {
+        let path = std::env::temp_dir().join(format!(
+            "acl-spent-{}-{}",
+            std::process::id(),
+            SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_nanos()
+        ));
+
+        {
+            let store = FileSpentTokenStore::open(&path).unwrap();
+            assert_eq!(store.insert(&[1u8; 32]), Ok(true));
+            assert_eq!(store.insert(&[1u8; 32]), Ok(false));
+            assert_eq!(store.insert(&[2u8; 32]), Ok(true));
+        }
+        OpenOptions::new().append(true).open(&path).unwrap().write_all(&[3u8; 7]).unwrap();
+
+        let store = FileSpentTokenStore::open(&path).unwrap();
+        assert_eq!(store.contains(&[1u8; 32]), Ok(true));
+        assert_eq!(store.contains(&[2u8; 32]), Ok(true));
+        assert_eq!(store.insert(&[3u8; 32]), Ok(true));
+        drop(store);
+
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), 96);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn file_store_cuts_back_failed_appends() {
+        let path = std::env::temp_dir().join(format!(
+            "acl-spent-truncated-{}-{}",
+            std::process::id(),
+            SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_nanos()
+        ));
+
+        let store = FileSpentTokenStore::open(&path).unwrap();
+        assert_eq!(store.insert(&[1u8; 32]), Ok(true));
+
+        // what an append that failed halfway leaves behind
+        OpenOptions::new().append(true).open(&path).unwrap().write_all(&[2u8; 13]).unwrap();
+        assert_eq!(store.insert(&[3u8; 32]), Ok(true));
+        drop(store);
+
+        let store = FileSpentTokenStore::open(&path).unwrap();
+        assert_eq!(store.contains(&[1u8; 32]), Ok(true));
+        assert_eq!(store.contains(&[3u8; 32]), Ok(true));
+        assert_eq!(store.insert(&[3u8; 32]), Ok(false));
+        drop(store);
+
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), 64);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn verifier_enforces_rate_limits() {
+        let signing_key = SigningKey::from_bytes(&[7u8; 32]);
+        let key = VerifyingKey::from(&signing_key);
+        let verifier = Verifier::new(&key, MemorySpentTokenStore::new());
+        let attributes = vec![Scalar::from(31337u16), Scalar::ONE, Scalar::ONE, Scalar::ZERO];
+
+        let policy = |epoch: &[u8]| PresentationPolicy {
+            rate_limit: Some(RateLimit {
+                secret: 0,
+                epoch: epoch.to_vec(),
+                limit: 3,
+            }),
+            ..Default::default()
+        };
+        let show = |epoch: &[u8], show: u32| {
+            // every show uses a fresh credential for the same secret
+            let token = issue(&signing_key, &schema(), attributes.clone());
+            let presentation =
+                Presentation::new_rate_limited(&mut OsRng, &token, &key, &schema(), &policy(epoch), show, b"nonce")
+                    .unwrap();
+            verifier.verify_presentation(&presentation, &schema(), &policy(epoch), b"nonce")
+        };
+
+        for j in 0..3 {
+            assert_eq!(show(b"monday", j), Ok(()));
+        }
+        assert_eq!(show(b"monday", 1), Err(VerifyingError::RateLimited));
+        assert_eq!(show(b"tuesday", 1), Ok(()));
+
+        // a presentation over the limit leaves its token unspent
+        let token = issue(&signing_key, &schema(), attributes.clone());
+        let over = Presentation::new_rate_limited(&mut OsRng, &token, &key, &schema(), &policy(b"monday"), 2, b"nonce")
+            .unwrap();
+        assert_eq!(
+            verifier.verify_presentation(&over, &schema(), &policy(b"monday"), b"nonce"),
+            Err(VerifyingError::RateLimited)
+        );
+        let later = Presentation::new_rate_limited(&mut OsRng, &token, &key, &schema(), &policy(b"tuesday"), 2, b"nonce")
+            .unwrap();
+        assert_eq!(verifier.verify_presentation(&later, &schema(), &policy(b"tuesday"), b"nonce"), Ok(()));
+        assert_eq!(
+            verifier.verify_presentation(&later, &schema(), &policy(b"tuesday"), b"nonce"),
+            Err(VerifyingError::Replayed)
+        );
+
+        let token = issue(&signing_key, &schema(), attributes.clone());
+        assert_eq!(
+            Presentation::new_rate_limited(&mut OsRng, &token, &key, &schema(), &policy(b"monday"), 3, b"nonce")
+                .unwrap_err(),
+            UserError::ShowIndex
+        );
+        assert_eq!(
+            Presentation::new(&mut OsRng, &token, &key, &schema(), &policy(b"monday"), b"nonce").unwrap_err(),
+            UserError::ShowIndex
+        );
+
+        let mut forged =
+            Presentation::new_rate_limited(&mut OsRng, &token, &key, &schema(), &policy(b"monday"), 0, b"nonce")
+                .unwrap();
+        forged.rate_limit.as_mut().unwrap().tag += RistrettoPoint::mul_base(&Scalar::ONE);
+        assert_eq!(
+            verifier.verify_presentation(&forged, &schema(), &policy(b"monday"), b"nonce"),
+            Err(VerifyingError::InvalidProof)
+        );
+    }
+
+    #[test]
+    fn verifier_rejects_replays() {
+        let signing_key = SigningKey::from_bytes(&[7u8; 32]);
+        let key = VerifyingKey::from(&signing_key);
+        let verifier = Verifier::new(&key, MemorySpentTokenStore::new());
+        let token = issue(&signing_key, &schema(), vec![Scalar::ONE; 4]);
+
+        let spend = token.spend(&mut OsRng, &key, &schema(), 0, &[], b"nonce 1").unwrap();
+        assert_eq!(verifier.verify_spend(&spend, &schema(), 0, b"nonce 1"), Ok(()));
+        assert_eq!(
+            verifier.verify_spend(&spend, &schema(), 0, b"nonce 1"),
+            Err(VerifyingError::Replayed)
+        );
+
+        let spend = token.spend(&mut OsRng, &key, &schema(), 0, &[], b"nonce 2").unwrap();
+        assert_eq!(
+            verifier.verify_spend(&spend, &schema(), 0, b"nonce 2"),
+            Err(VerifyingError::Replayed)
+        );
+        assert_eq!(
+            verifier.verify_prehashed(&token.hashed_message, &token.blinded_commitment, &token.signature),
+            Err(VerifyingError::Replayed)
+        );
+    }
+}
@@ -0,0 +1,504 @@
+use crate::errors::SigningError;
+use crate::signing::{PreSignature, PrepareMessage, SignerState, SigningKey};
+use crate::verifying::VerifyingKey;
+
+use curve25519_dalek::constants::RISTRETTO_BASEPOINT_POINT;
+use curve25519_dalek::ristretto::RistrettoPoint;
+use curve25519_dalek::scalar::Scalar;
+use curve25519_dalek::traits::VartimeMultiscalarMul;
+
+use rand_core::{CryptoRng, RngCore};
+
+use serde::{Deserialize, Serialize};
+
+// In threshold issuance the signing scalar x is Shamir-shared among n nodes,
+// and any t of them can sign together. Of the signer's values, only the nonce
+// u and the response r = u - c*x involve x. So each signing node i picks its
+// own u_i and answers with r_i = u_i - c*l_i*x_i, where l_i is its Lagrange
+// coefficient in the signing set; then a = sum(u_i*G) and r = sum(r_i), and
+// the user sees an ordinary ACL run.
+//
+// A coordinator (e.g. the issuance front end) runs the rest of the signer's
+// side: it picks d, s1, s2 and rnd, talks to the user, and checks every r_i
+// against the node's public share. It never learns x, but it does learn d,
+// so it has to be trusted with unforgeability like a single signer is.
+//
+// KeyShare is one node's share of the signing scalar
+#[derive(Clone)]
+pub struct KeyShare {
+    pub(crate) index: u32,
+    pub(crate) scalar: Scalar,
+    pub(crate) public: PublicShares,
+}
+
+// PublicShares is the public side of a sharing: the threshold, the joint
+// verifying key, and each node's share times G, in node order (node i at
+// position i - 1). Deserializing fails unless 1 <= threshold <= shares.len().
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "UncheckedPublicShares")]
+pub struct PublicShares {
+    pub(crate) threshold: u32,
+    pub(crate) key: RistrettoPoint,
+    pub(crate) shares: Vec<RistrettoPoint>,
+}
+
+#[derive(Deserialize)]
+struct UncheckedPublicShares {
+    threshold: u32,
+    key: RistrettoPoint,
+    shares: Vec<RistrettoPoint>,
+}
+
+// NonceShare is a node's nonce for one signing session. respond takes it by
+// value, since answering two challenges with one nonce reveals the share.
+pub struct NonceShare {
+    u: Scalar,
+}
+
+// ShareChallenge is what the coordinator sends the signing nodes once the user
+// has answered: the challenge for the key part, and the signing set it was
+// made for
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct ShareChallenge {
+    pub c: Scalar,
+    pub signers: Vec<u32>,
+}
+
+// ThresholdSignerState is the coordinator's state for one session
+pub struct ThresholdSignerState {
+    state: SignerState,
+    nonces: Vec<(u32, RistrettoPoint)>,
+    challenge: Option<Scalar>,
+}
+
+pub struct ThresholdCoordinator {
+    public: PublicShares,
+}
+
+impl SigningKey {
+    // split shares the signing scalar among shares nodes so that any
+    // threshold of them can sign, with a trusted dealer. The caller must
+    // destroy this key once the shares are handed out.
+    pub fn split<R: RngCore + CryptoRng>(
+        &self,
+        rng: &mut R,
+        threshold: u32,
+        shares: u32,
+    ) -> Result<Vec<KeyShare>, SigningError> {
+        if threshold == 0 || threshold > shares {
+            return Err(SigningError::Threshold);
+        }
+
+        let coefficients: Vec<Scalar> = [self.scalar]
+            .into_iter()
+            .chain((1..threshold).map(|_| Scalar::random(rng)))
+            .collect();
+        let scalars: Vec<Scalar> = (1..=shares).map(|index| evaluate(&coefficients, index)).collect();
+
+        let public = PublicShares {
+            threshold,
+            key: VerifyingKey::from(self).point,
+            shares: scalars.iter().map(RistrettoPoint::mul_base).collect(),
+        };
+
+        Ok(scalars
+            .into_iter()
+            .zip(1..)
+            .map(|(scalar, index)| KeyShare {
+                index,
+                scalar,
+                public: public.clone(),
+            })
+            .collect())
+    }
+}
+
+impl KeyShare {
+    pub fn index(&self) -> u32 {
+        self.index
+    }
+
+    pub fn public_shares(&self) -> &PublicShares {
+        &self.public
+    }
+
+    // commit starts a signing session, returning the nonce to keep and
+    // u_i*G to send to the coordinator
+    pub fn commit<R: RngCore + CryptoRng>(&self, rng: &mut R) -> (NonceShare, RistrettoPoint) {
+        let u = Scalar::random(rng);
+        (NonceShare { u }, RistrettoPoint::mul_base(&u))
+    }
+
+    // respond answers the coordinator's challenge, after checking that the
+    // signing set has at least threshold nodes and includes this one
+    pub fn respond(&self, nonce: NonceShare, challenge: &ShareChallenge) -> Result<Scalar, SigningError> {
+        if challenge.signers.len() < self.public.threshold as usize || !challenge.signers.contains(&self.index) {
+            return Err(SigningError::Threshold);
+        }
+
+        let lagrange = lagrange(&challenge.signers, self.index, self.public.shares.len())?;
+        Ok(nonce.u - challenge.c * lagrange * self.scalar)
+    }
+}
+
+impl PublicShares {
+    pub fn threshold(&self) -> u32 {
+        self.threshold
+    }
+
+    pub fn verifying_key(&self) -> VerifyingKey {
+        VerifyingKey { point: self.key }
+    }
+}
+
+impl TryFrom<UncheckedPublicShares> for PublicShares {
+    type Error = SigningError;
+
+    fn try_from(public: UncheckedPublicShares) -> Result<PublicShares, SigningError> {
+        if public.threshold == 0 || public.threshold as usize > public.shares.len() {
+            return Err(SigningError::Threshold);
+        }
+
+        Ok(PublicShares {
+            threshold: public.threshold,
+            key: public.key,
+            shares: public.shares,
+        })
+    }
+}
+
+impl ThresholdCoordinator {
+    pub fn new(public: PublicShares) -> ThresholdCoordinator {
+        ThresholdCoordinator { public }
+    }
+
+    pub fn verifying_key(&self) -> VerifyingKey {
+        self.public.verifying_key()
+    }
+
+    // prepare combines the nonces of at least threshold nodes, given as
+    // (node index, u_i*G), into the prepare message for the user
+    pub fn prepare<R: RngCore + CryptoRng>(
+        &self,
+        rng: &mut R,
+        commitment: &RistrettoPoint,
+        nonces: &[(u32, RistrettoPoint)],
+    ) -> Result<(ThresholdSignerState, Vec<u8>), SigningError> {
+        self.signers(nonces)?;
+
+        let state = SignerState::random(rng);
+        let a = nonces.iter().map(|(_, nonce)| nonce).sum();
+        let msg = PrepareMessage::new(&state, a, commitment);
+
+        Ok((
+            ThresholdSignerState {
+                state,
+                nonces: nonces.to_vec(),
+                challenge: None,
+            },
+            msg.to_bytes(),
+        ))
+    }
+
+    // challenge turns the user's challenge into the nodes' challenge. A
+    // session only ever answers one challenge.
+    pub fn challenge(
+        &self,
+        state: &mut ThresholdSignerState,
+        challenge_bytes: &[u8],
+    ) -> Result<ShareChallenge, SigningError> {
+        if state.challenge.is_some() {
+            return Err(SigningError::Threshold);
+        }
+
+        let e = Scalar::from_canonical_bytes(challenge_bytes.try_into()?)
+            .into_option()
+            .ok_or(SigningError::ScalarFormat)?;
+        let c = e - state.state.d;
+        state.challenge = Some(c);
+
+        Ok(ShareChallenge {
+            c,
+            signers: state.nonces.iter().map(|(index, _)| *index).collect(),
+        })
+    }
+
+    // presign checks every node's response against its public share, as
+    // r_i*G + c*l_i*X_i = u_i*G, and combines them into the presignature for
+    // the user. responses must be in the order of the nonces given to
+    // prepare.
+    pub fn presign(&self, state: ThresholdSignerState, responses: &[Scalar]) -> Result<Vec<u8>, SigningError> {
+        let c = state.challenge.ok_or(SigningError::Threshold)?;
+        self.check_responses(&state.nonces, &c, responses)?;
+
+        Ok(PreSignature {
+            c,
+            d: state.state.d,
+            r: responses.iter().sum(),
+            s1: state.state.s1,
+            s2: state.state.s2,
+        }
+        .to_bytes())
+    }
+
+    // check_responses checks every node's response to the challenge c against
+    // its public share, as r_i*G + c*l_i*X_i = u_i*G. responses must be in the
+    // order of nonces.
+    pub(crate) fn check_responses(
+        &self,
+        nonces: &[(u32, RistrettoPoint)],
+        c: &Scalar,
+        responses: &[Scalar],
+    ) -> Result<(), SigningError> {
+        if responses.len() != nonces.len() {
+            return Err(SigningError::Threshold);
+        }
+
+        let signers: Vec<u32> = nonces.iter().map(|(index, _)| *index).collect();
+
+        for ((index, nonce), response) in nonces.iter().zip(responses) {
+            let lagrange = lagrange(&signers, *index, self.public.shares.len())?;
+            let expected = RistrettoPoint::vartime_multiscalar_mul(
+                [*response, c * lagrange],
+                [RISTRETTO_BASEPOINT_POINT, self.public.shares[*index as usize - 1]],
+            );
+
+            if expected != *nonce {
+                return Err(SigningError::InvalidShare { index: *index });
+            }
+        }
+
+        Ok(())
+    }
+
+    // signers checks that nonces come from at least threshold nodes, and
+    // returns their indices
+    pub(crate) fn signers(&self, nonces: &[(u32, RistrettoPoint)]) -> Result<Vec<u32>, SigningError> {
+        let signers: Vec<u32> = nonces.iter().map(|(index, _)| *index).collect();
+        let first = *signers.first().ok_or(SigningError::Threshold)?;
+        if signers.len() < self.public.threshold as usize {
+            return Err(SigningError::Threshold);
+        }
+        lagrange(&signers, first, self.public.shares.len())?;
+
+        Ok(signers)
+    }
+}
+
+// evaluate evaluates the polynomial with the given coefficients, lowest
+// degree first, at x
+pub(crate) fn evaluate(coefficients: &[Scalar], x: u32) -> Scalar {
+    let x = Scalar::from(x);
+    coefficients
+        .iter()
+        .rev()
+        .fold(Scalar::ZERO, |acc, coefficient| acc * x + coefficient)
+}
+
+// lagrange computes the coefficient of node index when interpolating at 0
+// from the nodes in signers, which must be distinct, include index, and lie
+// in 1..=nodes
+pub(crate) fn lagrange(signers: &[u32], index: u32, nodes: usize) -> Result<Scalar, SigningError> {
+    if !signers.contains(&index)
+        || signers.iter().any(|signer| *signer == 0 || *signer as usize > nodes)
+        || signers
+            .iter()
+            .enumerate()
+            .any(|(position, signer)| signers[..position].contains(signer))
+    {
+        return Err(SigningError::Threshold);
+    }
+
+    let (numerator, denominator) = signers
+        .iter()
+        .filter(|signer| **signer != index)
+        .fold((Scalar::ONE, Scalar::ONE), |(numerator, denominator), signer| {
+            (
+                numerator * Scalar::from(*signer),
+                denominator * (Scalar::from(*signer) - Scalar::from(index)),
+            )
+        });
+
+    Ok(numerator * denominator.invert())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::user::UserParameters;
+    use rand_core::OsRng;
+    use std::sync::mpsc::{channel, Receiver, Sender};
+    use std::thread;
+
+    enum Request {
+        Commit,
+        Respond(ShareChallenge),
+    }
+
+    enum Response {
+        Nonce(u32, RistrettoPoint),
+        Share(u32, Result<Scalar, SigningError>),
+    }
+
+    // node runs one signing node on its own thread, answering the
+    // coordinator's requests until the channel closes
+    fn node(share: KeyShare, requests: Receiver<Request>, responses: Sender<Response>) {
+        thread::spawn(move || {
+            let mut nonce = None;
+            for request in requests {
+                let response = match request {
+                    Request::Commit => {
+                        let (state, point) = share.commit(&mut OsRng);
+                        nonce = Some(state);
+                        Response::Nonce(share.index(), point)
+                    }
+                    Request::Respond(challenge) => Response::Share(
+                        share.index(),
+                        share.respond(nonce.take().expect("no session"), &challenge),
+                    ),
+                };
+                responses.send(response).unwrap();
+            }
+        });
+    }
+
+    // issue runs an issuance session between a user and the coordinator, which
+    // talks to the nodes in signers over the simulated network
+    fn issue(
+        coordinator: &ThresholdCoordinator,
+        network: &[Sender<Request>],
+        inbox: &Receiver<Response>,
+        signers: &[u32],
+        corrupt: Option<u32>,
+    ) -> Result<(), SigningError> {
+        let user_params = UserParameters {
+            key: coordinator.verifying_key(),
+        };
+        let commitment = RistrettoPoint::random(&mut OsRng);
+
+        for signer in signers {
+            network[*signer as usize - 1].send(Request::Commit).unwrap();
+        }
+        let mut nonces: Vec<(u32, RistrettoPoint)> = signers
+            .iter()
+            .map(|_| match inbox.recv().unwrap() {
+                Response::Nonce(index, point) => (index, point),
+                Response::Share(..) => panic!("unexpected share"),
+            })
+            .collect();
+        nonces.sort_by_key(|(index, _)| *index);
+
+        let (mut state, prepare_message) = coordinator.prepare(&mut OsRng, &commitment, &nonces)?;
+        let (us, challenge) = user_params
+            .compute_challenge(&mut OsRng, &commitment, &[0u8; 64], &prepare_message)
+            .unwrap();
+
+        let share_challenge = coordinator.challenge(&mut state, &challenge)?;
+        for (index, _) in nonces.iter() {
+            network[*index as usize - 1]
+                .send(Request::Respond(share_challenge.clone()))
+                .unwrap();
+        }
+        let mut responses: Vec<(u32, Scalar)> = nonces
+            .iter()
+            .map(|_| match inbox.recv().unwrap() {
+                Response::Share(index, share) => (index, share.unwrap()),
+                Response::Nonce(..) => panic!("unexpected nonce"),
+            })
+            .collect();
+        responses.sort_by_key(|(index, _)| *index);
+
+        let responses: Vec<Scalar> = responses
+            .into_iter()
+            .map(|(index, share)| if Some(index) == corrupt { share + Scalar::ONE } else { share })
+            .collect();
+
+        let presignature = coordinator.presign(state, &responses)?;
+        let credential = user_params.compute_signature(&us, &presignature).unwrap();
+        assert_eq!(
+            credential.verify(&coordinator.verifying_key()),
+            Ok(())
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn threshold_issuance_over_simulated_network() {
+        let signing_key = SigningKey::from_bytes(&[7u8; 32]);
+        let shares = signing_key.split(&mut OsRng, 3, 5).unwrap();
+        let coordinator = ThresholdCoordinator::new(shares[0].public_shares().clone());
+        assert_eq!(coordinator.verifying_key().point, VerifyingKey::from(&signing_key).point);
+
+        let (outbox, inbox) = channel();
+        let network: Vec<Sender<Request>> = shares
+            .into_iter()
+            .map(|share| {
+                let (sender, receiver) = channel();
+                node(share, receiver, outbox.clone());
+                sender
+            })
+            .collect();
+
+        assert_eq!(issue(&coordinator, &network, &inbox, &[1, 2, 3], None), Ok(()));
+        assert_eq!(issue(&coordinator, &network, &inbox, &[2, 4, 5], None), Ok(()));
+        assert_eq!(issue(&coordinator, &network, &inbox, &[1, 2, 3, 4, 5], None), Ok(()));
+        assert_eq!(
+            issue(&coordinator, &network, &inbox, &[1, 3], None),
+            Err(SigningError::Threshold)
+        );
+        assert_eq!(
+            issue(&coordinator, &network, &inbox, &[1, 3, 5], Some(3)),
+            Err(SigningError::InvalidShare { index: 3 })
+        );
+    }
+
+    #[test]
+    fn challenges_are_answered_once() {
+        let signing_key = SigningKey::from_bytes(&[7u8; 32]);
+        let shares = signing_key.split(&mut OsRng, 2, 3).unwrap();
+        let coordinator = ThresholdCoordinator::new(shares[0].public_shares().clone());
+
+        let nonces: Vec<(u32, RistrettoPoint)> = shares[..2]
+            .iter()
+            .map(|share| (share.index(), share.commit(&mut OsRng).1))
+            .collect();
+        let (mut state, _) = coordinator
+            .prepare(&mut OsRng, &RistrettoPoint::random(&mut OsRng), &nonces)
+            .unwrap();
+
+        assert!(coordinator.challenge(&mut state, &[1u8; 32]).is_ok());
+        assert_eq!(coordinator.challenge(&mut state, &[1u8; 32]), Err(SigningError::Threshold));
+        assert_eq!(signing_key.split(&mut OsRng, 4, 3).err(), Some(SigningError::Threshold));
+
+        // nodes refuse a signing set below the threshold or without them
+        let short = ShareChallenge {
+            c: Scalar::ONE,
+            signers: vec![1],
+        };
+        assert_eq!(shares[0].respond(shares[0].commit(&mut OsRng).0, &short), Err(SigningError::Threshold));
+        let others = ShareChallenge {
+            c: Scalar::ONE,
+            signers: vec![2, 3],
+        };
+        assert_eq!(shares[0].respond(shares[0].commit(&mut OsRng).0, &others), Err(SigningError::Threshold));
+    }
+
+    #[test]
+    fn zero_thresholds_are_rejected() {
+        let signing_key = SigningKey::from_bytes(&[7u8; 32]);
+        let mut public = signing_key.split(&mut OsRng, 2, 3).unwrap()[0].public_shares().clone();
+        public.threshold = 0;
+
+        let json = serde_json::to_string(&public).unwrap();
+        assert!(serde_json::from_str::<PublicShares>(&json).is_err());
+
+        let coordinator = ThresholdCoordinator::new(public);
+        assert_eq!(
+            coordinator
+                .prepare(&mut OsRng, &RistrettoPoint::random(&mut OsRng), &[])
+                .err(),
+            Some(SigningError::Threshold)
+        );
+    }
+}
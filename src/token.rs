@@ -0,0 +1,313 @@
+use crate::attributes::{Opening, Schema};
+use crate::errors::{UserError, VerifyingError};
+use crate::presentation::{delta_tag, relate, transcript};
+use crate::proof::{Proof, Statement};
+use crate::signature::{Credential, Signature};
+use crate::verifying::VerifyingKey;
+
+use curve25519_dalek::constants::RISTRETTO_BASEPOINT_POINT;
+use curve25519_dalek::ristretto::RistrettoPoint;
+use curve25519_dalek::scalar::Scalar;
+use curve25519_dalek::traits::Identity;
+
+use rand_core::{CryptoRng, RngCore};
+
+use serde::{Deserialize, Serialize};
+
+use sha2::{Digest, Sha512};
+
+// Token is everything the user keeps from one issuance: the signature and
+// blinded commitment, the message they're bound to, the blinding secrets gamma
+// and rnd, and the opening of the commitment that was signed.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Token {
+    pub signature: Signature,
+    pub blinded_commitment: RistrettoPoint,
+    pub hashed_message: Vec<u8>,
+    pub gamma: Scalar,
+    pub rnd: Scalar,
+    pub opening: Opening,
+}
+
+// SpendProof shows a token once. Besides proving that the token certifies its
+// revealed attributes, it answers a challenge derived from the verifier's
+// context with response = id + challenge * r, where id is the user id
+// attribute and r the commitment's blinding factor. One response reveals
+// nothing about id, but two responses for the same token reveal it, see
+// detect_double_spend.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct SpendProof {
+    pub signature: Signature,
+    pub blinded_commitment: RistrettoPoint,
+    pub hashed_message: Vec<u8>,
+    pub revealed: Vec<(usize, Scalar)>,
+    pub challenge: Scalar,
+    pub response: Scalar,
+    pub(crate) delta_tag: RistrettoPoint,
+    pub(crate) proof: Proof,
+}
+
+impl Token {
+    // new bundles the output of compute_signature with the opening of the
+    // commitment that was signed
+    pub fn new(credential: Credential, opening: Opening) -> Token {
+        Token {
+            signature: credential.signature,
+            blinded_commitment: credential.blinded_commitment,
+            hashed_message: credential.hashed_message,
+            gamma: credential.gamma,
+            rnd: credential.rnd,
+            opening,
+        }
+    }
+
+    // spend shows the token to a verifier, revealing the attributes at
+    // revealed. id_index is the schema index of the user id attribute, which
+    // must stay hidden. context should be unique per show (e.g. a verifier
+    // nonce), since two shows with the same context get the same challenge.
+    pub fn spend<R: RngCore + CryptoRng>(
+        &self,
+        rng: &mut R,
+        key: &VerifyingKey,
+        schema: &Schema,
+        id_index: usize,
+        revealed: &[usize],
+        context: &[u8],
+    ) -> Result<SpendProof, UserError> {
+        if self.opening.attributes.len() != schema.len() {
+            return Err(UserError::OpeningLength);
+        }
+
+        let revealed: Vec<(usize, Scalar)> = revealed
+            .iter()
+            .map(|index| Ok((*index, *self.opening.attributes.get(*index).ok_or(UserError::AttributeIndex)?)))
+            .collect::<Result<_, UserError>>()?;
+
+        let challenge = spend_challenge(&self.blinded_commitment, &self.signature, context);
+        let id = *self.opening.attributes.get(id_index).ok_or(UserError::AttributeIndex)?;
+        let response = id + challenge * self.opening.blinding;
+
+        let delta_tag = delta_tag(self);
+        let statement = spend_statement(
+            schema,
+            &self.blinded_commitment,
+            &delta_tag,
+            &revealed,
+            (id_index, &challenge, &response),
+            Some(self),
+        )
+        .ok_or(UserError::AttributeIndex)?;
+
+        Ok(SpendProof {
+            signature: self.signature,
+            blinded_commitment: self.blinded_commitment,
+            hashed_message: self.hashed_message.clone(),
+            proof: statement.prove(
+                rng,
+                transcript(b"acl spend", key, &self.signature, &self.hashed_message, context),
+            ),
+            revealed,
+            challenge,
+            response,
+            delta_tag,
+        })
+    }
+}
+
+impl SpendProof {
+    pub fn verify(
+        &self,
+        key: &VerifyingKey,
+        schema: &Schema,
+        id_index: usize,
+        context: &[u8],
+    ) -> Result<(), VerifyingError> {
+        key.verify_prehashed(&self.hashed_message, &self.blinded_commitment, &self.signature)?;
+
+        // an identity delta_tag can only come from proving with delta = 0, see
+        // relate
+        if self.challenge != spend_challenge(&self.blinded_commitment, &self.signature, context)
+            || self.delta_tag == RistrettoPoint::identity()
+        {
+            return Err(VerifyingError::InvalidProof);
+        }
+
+        let statement = spend_statement(
+            schema,
+            &self.blinded_commitment,
+            &self.delta_tag,
+            &self.revealed,
+            (id_index, &self.challenge, &self.response),
+            None,
+        )
+        .ok_or(VerifyingError::AttributeIndex)?;
+
+        if statement.verify(
+            transcript(b"acl spend", key, &self.signature, &self.hashed_message, context),
+            &self.proof,
+        ) {
+            Ok(())
+        } else {
+            Err(VerifyingError::InvalidProof)
+        }
+    }
+}
+
+// detect_double_spend recovers the user id attribute from two verified spends
+// of the same token with different challenges: from s1 = id + c1*r and
+// s2 = id + c2*r it follows that r = (s1 - s2) / (c1 - c2). It returns None if
+// the spends are of different tokens or share a challenge.
+pub fn detect_double_spend(first: &SpendProof, second: &SpendProof) -> Option<Scalar> {
+    if first.signature != second.signature
+        || first.blinded_commitment != second.blinded_commitment
+        || first.challenge == second.challenge
+    {
+        return None;
+    }
+
+    let blinding = (first.response - second.response) * (first.challenge - second.challenge).invert();
+    Some(first.response - first.challenge * blinding)
+}
+
+fn spend_challenge(blinded_commitment: &RistrettoPoint, signature: &Signature, context: &[u8]) -> Scalar {
+    let mut hash = Sha512::new();
+    hash.update(b"acl spend challenge");
+    hash.update(blinded_commitment.compress().as_bytes());
+    hash.update(signature.to_bytes());
+    hash.update(context);
+    Scalar::from_hash(hash)
+}
+
+// spend_statement is the credential relation plus response*G = id*G + challenge*r*G
+fn spend_statement(
+    schema: &Schema,
+    blinded_commitment: &RistrettoPoint,
+    delta_tag: &RistrettoPoint,
+    revealed: &[(usize, Scalar)],
+    (id_index, challenge, response): (usize, &Scalar, &Scalar),
+    token: Option<&Token>,
+) -> Option<Statement> {
+    let mut statement = Statement::default();
+    let vars = relate(&mut statement, schema, blinded_commitment, delta_tag, revealed, token)?;
+    let id = (*vars.attributes.get(id_index)?)?;
+
+    statement.constrain(
+        RistrettoPoint::mul_base(response),
+        vec![
+            (id, RISTRETTO_BASEPOINT_POINT),
+            (vars.blinding, RistrettoPoint::mul_base(challenge)),
+        ],
+    );
+    Some(statement)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::signing::SigningKey;
+    use crate::user::UserParameters;
+    use rand_core::OsRng;
+
+    pub(crate) fn schema() -> Schema {
+        Schema::new(&["user id", "type", "sports", "tech"])
+    }
+
+    // issue runs the whole issuance protocol for the given attributes
+    pub(crate) fn issue(signing_key: &SigningKey, schema: &Schema, attributes: Vec<Scalar>) -> Token {
+        let user_params = UserParameters {
+            key: VerifyingKey::from(signing_key),
+        };
+        let opening = Opening::new(&mut OsRng, attributes);
+        let commitment = opening.commit(schema);
+
+        let (ss, prepare_message) = signing_key.prepare(&commitment).unwrap();
+        let (us, challenge) = user_params
+            .compute_challenge(&mut OsRng, &commitment, &[0u8; 64], &prepare_message)
+            .unwrap();
+        let presignature = signing_key.compute_presignature(ss, &challenge).unwrap();
+
+        Token::new(user_params.compute_signature(&us, &presignature).unwrap(), opening)
+    }
+
+    fn attributes() -> Vec<Scalar> {
+        vec![Scalar::from(1234u16), Scalar::from(2u8), Scalar::ONE, Scalar::ZERO]
+    }
+
+    #[test]
+    fn double_spend_reveals_the_user_id() {
+        let signing_key = SigningKey::from_bytes(&[7u8; 32]);
+        let key = VerifyingKey::from(&signing_key);
+        let token = issue(&signing_key, &schema(), attributes());
+
+        let first = token.spend(&mut OsRng, &key, &schema(), 0, &[1], b"nonce 1").unwrap();
+        let second = token.spend(&mut OsRng, &key, &schema(), 0, &[], b"nonce 2").unwrap();
+
+        assert_eq!(first.verify(&key, &schema(), 0, b"nonce 1"), Ok(()));
+        assert_eq!(second.verify(&key, &schema(), 0, b"nonce 2"), Ok(()));
+        assert_eq!(first.revealed, vec![(1, Scalar::from(2u8))]);
+
+        assert_eq!(detect_double_spend(&first, &second), Some(Scalar::from(1234u16)));
+        assert_eq!(detect_double_spend(&first, &first), None);
+
+        let other = issue(&signing_key, &schema(), attributes());
+        let other = other.spend(&mut OsRng, &key, &schema(), 0, &[], b"nonce 3").unwrap();
+        assert_eq!(detect_double_spend(&first, &other), None);
+    }
+
+    #[test]
+    fn invalid_spends_are_rejected() {
+        let signing_key = SigningKey::from_bytes(&[7u8; 32]);
+        let key = VerifyingKey::from(&signing_key);
+        let token = issue(&signing_key, &schema(), attributes());
+        let spend = token.spend(&mut OsRng, &key, &schema(), 0, &[1], b"nonce").unwrap();
+
+        assert_eq!(spend.verify(&key, &schema(), 0, b"other nonce"), Err(VerifyingError::InvalidProof));
+        assert_eq!(spend.verify(&key, &schema(), 2, b"nonce"), Err(VerifyingError::InvalidProof));
+
+        let mut wrong_response = spend.clone();
+        wrong_response.response += Scalar::ONE;
+        assert_eq!(wrong_response.verify(&key, &schema(), 0, b"nonce"), Err(VerifyingError::InvalidProof));
+
+        let mut wrong_attribute = spend.clone();
+        wrong_attribute.revealed[0].1 = Scalar::from(64u8);
+        assert_eq!(wrong_attribute.verify(&key, &schema(), 0, b"nonce"), Err(VerifyingError::InvalidProof));
+
+        let other_key = VerifyingKey::from(&SigningKey::from_bytes(&[8u8; 32]));
+        assert_eq!(
+            spend.verify(&other_key, &schema(), 0, b"nonce"),
+            Err(VerifyingError::Invalid)
+        );
+
+        assert_eq!(
+            token.spend(&mut OsRng, &key, &schema(), 1, &[1], b"nonce").unwrap_err(),
+            UserError::AttributeIndex
+        );
+    }
+
+    #[test]
+    fn zero_witnesses_do_not_forge_spends() {
+        let signing_key = SigningKey::from_bytes(&[7u8; 32]);
+        let key = VerifyingKey::from(&signing_key);
+        let token = issue(&signing_key, &schema(), attributes());
+        let honest = token.spend(&mut OsRng, &key, &schema(), 0, &[3], b"nonce").unwrap();
+
+        // delta = 0, with every other witness and every revealed value zero,
+        // satisfies the signature relation for anyone's blinded commitment
+        let forged = Token {
+            gamma: Scalar::ZERO,
+            rnd: Scalar::ZERO,
+            opening: Opening {
+                attributes: vec![Scalar::ZERO; 4],
+                blinding: Scalar::ZERO,
+            },
+            ..token.clone()
+        };
+        let spend = forged.spend(&mut OsRng, &key, &schema(), 0, &[3], b"nonce").unwrap();
+        assert_eq!(spend.delta_tag, RistrettoPoint::identity());
+        assert_eq!(spend.verify(&key, &schema(), 0, b"nonce"), Err(VerifyingError::InvalidProof));
+
+        let mut disguised = spend.clone();
+        disguised.delta_tag = honest.delta_tag;
+        assert_eq!(disguised.verify(&key, &schema(), 0, b"nonce"), Err(VerifyingError::InvalidProof));
+    }
+}
@@ -1,7 +1,7 @@
-use crate::constants::{gen_h, gen_z};
+use crate::constants::{gen_h_table, gen_z_table};
 use crate::errors::UserError;
-use crate::signature::Signature;
-use crate::signing::{PreSignature, PrepareMessage};
+use crate::signature::{Credential, Signature};
+use crate::signing::{frame, unframe, PreSignature, PrepareMessage};
 use crate::verifying::{compute_challenge, VerifyingKey};
 
 use curve25519_dalek::ristretto::{CompressedRistretto, RistrettoPoint};
@@ -120,11 +120,11 @@ impl UserParameters {
             return Err(UserError::GammaZero);
         }
 
-        let xi = gen_z() * gamma;
+        let xi = gen_z_table() * &gamma;
         let xi1 = z1 * gamma;
         let xi2 = xi - xi1;
         let tau = Scalar::random(rng);
-        let eta = gen_z() * tau;
+        let eta = gen_z_table() * &tau;
 
         let t1 = Scalar::random(rng);
         let t2 = Scalar::random(rng);
@@ -134,7 +134,7 @@ impl UserParameters {
 
         let alpha = prepare_message.a + RistrettoPoint::mul_base(&t1) + self.key.point * t2;
         let beta1 = prepare_message.b1 * gamma + RistrettoPoint::mul_base(&t3) + t4 * xi1;
-        let beta2 = prepare_message.b2 * gamma + gen_h() * t5 + t4 * xi2;
+        let beta2 = prepare_message.b2 * gamma + gen_h_table() * &t5 + t4 * xi2;
 
         let epsilon = compute_challenge(&xi, &xi1, &alpha, &beta1, &beta2, &eta, &hashed_message);
 
@@ -163,7 +163,7 @@ impl UserParameters {
         &self,
         user_state: &UserState,
         presignature_bytes: &[u8],
-    ) -> Result<(Signature, RistrettoPoint, Scalar, Scalar), UserError> {
+    ) -> Result<Credential, UserError> {
         let presignature = PreSignature::try_from(presignature_bytes)?;
 
         let rho = presignature.r + user_state.t1;
@@ -189,11 +189,56 @@ impl UserParameters {
             &signature,
         )?;
 
-        Ok((
+        Ok(Credential {
             signature,
-            user_state.gamma * (user_state.commitment + RistrettoPoint::mul_base(&user_state.rnd)),
-            user_state.gamma,
-            user_state.rnd,
-        ))
+            blinded_commitment: user_state.gamma * (user_state.commitment + RistrettoPoint::mul_base(&user_state.rnd)),
+            hashed_message: user_state.hashed_message.clone(),
+            gamma: user_state.gamma,
+            rnd: user_state.rnd,
+        })
+    }
+
+    // compute_challenge_batch runs compute_challenge for every (commitment,
+    // hashed_message) against the framed prepare message from
+    // SigningKey::prepare_batch, with fresh blinding for every token
+    pub fn compute_challenge_batch<R: RngCore + CryptoRng>(
+        &self,
+        rng: &mut R,
+        requests: &[(&RistrettoPoint, &[u8])],
+        signer_message: &[u8],
+    ) -> Result<(Vec<UserState>, Vec<u8>), UserError> {
+        let signer_messages = unframe(signer_message, 128).ok_or(UserError::BatchSize)?;
+        if signer_messages.len() != requests.len() {
+            return Err(UserError::BatchSize);
+        }
+
+        let (states, challenges) = requests
+            .iter()
+            .zip(signer_messages)
+            .map(|((commitment, hashed_message), signer_message)| {
+                self.compute_challenge(rng, commitment, hashed_message, signer_message)
+            })
+            .collect::<Result<(Vec<UserState>, Vec<Vec<u8>>), UserError>>()?;
+
+        Ok((states, frame(&challenges)))
+    }
+
+    // compute_signature_batch runs compute_signature for every state against
+    // the framed presignatures from SigningKey::compute_presignature_batch
+    pub fn compute_signature_batch(
+        &self,
+        user_states: &[UserState],
+        presignatures: &[u8],
+    ) -> Result<Vec<Credential>, UserError> {
+        let presignatures = unframe(presignatures, 160).ok_or(UserError::BatchSize)?;
+        if presignatures.len() != user_states.len() {
+            return Err(UserError::BatchSize);
+        }
+
+        user_states
+            .iter()
+            .zip(presignatures)
+            .map(|(user_state, presignature)| self.compute_signature(user_state, presignature))
+            .collect()
     }
 }
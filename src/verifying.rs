@@ -1,13 +1,17 @@
 use crate::constants::{gen_h, gen_z};
-use crate::errors::VerifyingError;
-use crate::signature::Signature;
+use crate::errors::{BatchVerifyingError, VerifyingError};
+use crate::signature::{BatchableSignature, Signature};
 use crate::signing::SigningKey;
-use curve25519_dalek::ristretto::{CompressedRistretto, RistrettoPoint};
+use curve25519_dalek::constants::RISTRETTO_BASEPOINT_POINT;
+use curve25519_dalek::ristretto::{CompressedRistretto, RistrettoPoint, VartimeRistrettoPrecomputation};
 use curve25519_dalek::scalar::Scalar;
+use curve25519_dalek::traits::{IsIdentity, VartimeMultiscalarMul, VartimePrecomputedMultiscalarMul};
 use digest::{generic_array::typenum::U64, Digest};
+use serde::{Deserialize, Serialize};
 use sha2::Sha512;
+use std::sync::Arc;
 
-#[derive(Copy, Clone, Default)]
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
 pub struct VerifyingKey {
     pub(crate) point: RistrettoPoint,
 }
@@ -21,9 +25,21 @@ impl From<&SigningKey> for VerifyingKey {
 }
 
 impl VerifyingKey {
-    fn to_bytes(&self) -> Vec<u8> {
+    pub fn to_bytes(&self) -> Vec<u8> {
         Vec::from(self.point.compress().to_bytes())
     }
+
+    // key_id is a short, stable name for the key, e.g. for storing credentials
+    // by the key they were issued under
+    pub fn key_id(&self) -> [u8; 32] {
+        let mut hash = Sha512::new();
+        hash.update(b"acl key id");
+        hash.update(self.point.compress().as_bytes());
+
+        let mut id = [0u8; 32];
+        id.copy_from_slice(&hash.finalize()[..32]);
+        id
+    }
 }
 
 impl TryFrom<&[u8]> for VerifyingKey {
@@ -44,19 +60,37 @@ pub(crate) fn compute_challenge(
     eta: &RistrettoPoint,
     hashed_message: &[u8],
 ) -> Scalar {
+    compute_challenge_compressed(
+        &[
+            xi.compress(),
+            xi1.compress(),
+            alpha.compress(),
+            beta1.compress(),
+            beta2.compress(),
+            eta.compress(),
+        ],
+        hashed_message,
+    )
+}
+
+// compute_challenge_compressed is compute_challenge for callers that already
+// hold xi, xi1, alpha, beta1, beta2 and eta in compressed form
+fn compute_challenge_compressed(points: &[CompressedRistretto; 6], hashed_message: &[u8]) -> Scalar {
     let mut hash = Sha512::new();
-    
-    hash.update(xi.compress().to_bytes());
-    hash.update(xi1.compress().to_bytes());
-    hash.update(alpha.compress().to_bytes());
-    hash.update(beta1.compress().to_bytes());
-    hash.update(beta2.compress().to_bytes());
-    hash.update(eta.compress().to_bytes());
+
+    for point in points {
+        hash.update(point.to_bytes());
+    }
     hash.update(hashed_message);
 
     Scalar::from_bytes_mod_order_wide(hash.finalize().as_ref())
 }
 
+// Verification only ever handles public values (the key, the blinded
+// commitment and the signature), so everything below uses variable-time
+// multiscalar multiplication. The signer (signing.rs) and the user (user.rs)
+// multiply by their secret nonces and blinding factors, and must keep using
+// the constant-time operations and tables.
 impl VerifyingKey {
     pub fn verify_prehashed(
         &self,
@@ -64,20 +98,331 @@ impl VerifyingKey {
         commitment: &RistrettoPoint,
         sig: &Signature,
     ) -> Result<(), VerifyingError> {
-        let check = compute_challenge(
-            &sig.xi,
-            &commitment,
-            &(RistrettoPoint::mul_base(&sig.rho) + self.point * sig.omega),
-            &(RistrettoPoint::mul_base(&sig.sigma1) + commitment * sig.delta),
-            &(sig.sigma2 * gen_h() + (sig.xi - commitment) * sig.delta),
-            &(sig.mu * gen_z() + sig.xi * sig.delta),
-            hashed_message,
-        );
-
-        if check == sig.omega + sig.delta {
+        self.batchable(hashed_message, commitment, sig).map(|_| ())
+    }
+
+    // batchable verifies the signature and returns it with the points its
+    // challenge hashes, for verify_batch. The holder of a credential does this
+    // once, so that a verifier of many credentials doesn't have to.
+    pub fn batchable(
+        &self,
+        hashed_message: &[u8],
+        commitment: &RistrettoPoint,
+        sig: &Signature,
+    ) -> Result<BatchableSignature, VerifyingError> {
+        let alpha = RistrettoPoint::vartime_double_scalar_mul_basepoint(&sig.omega, &self.point, &sig.rho);
+
+        check_prehashed(alpha, hashed_message, commitment, sig)
+    }
+
+    // verify_batch and verify_batchable check many signatures under this key,
+    // see PrecomputedVerifyingKey
+    pub fn verify_batch(&self, batch: &[(&[u8], &RistrettoPoint, &Signature)]) -> Result<(), BatchVerifyingError> {
+        PrecomputedVerifyingKey::from(self).verify_batch(batch)
+    }
+
+    pub fn verify_batchable(
+        &self,
+        batch: &[(&[u8], &RistrettoPoint, &BatchableSignature)],
+    ) -> Result<(), BatchVerifyingError> {
+        PrecomputedVerifyingKey::from(self).verify_batchable(batch)
+    }
+}
+
+// PrecomputedVerifyingKey caches a variable-time lookup table for G and the
+// issuer's public point, the only fixed pair in the verification equations
+// that depends on the key. The table is shared between clones.
+#[derive(Clone)]
+pub struct PrecomputedVerifyingKey {
+    pub(crate) key: VerifyingKey,
+    pub(crate) precomputation: Arc<VartimeRistrettoPrecomputation>,
+}
+
+impl From<&VerifyingKey> for PrecomputedVerifyingKey {
+    fn from(key: &VerifyingKey) -> PrecomputedVerifyingKey {
+        PrecomputedVerifyingKey {
+            key: *key,
+            precomputation: Arc::new(VartimeRistrettoPrecomputation::new([RISTRETTO_BASEPOINT_POINT, key.point])),
+        }
+    }
+}
+
+// BatchEntry is a batch entry whose challenge hash checked out, with the
+// weights of its four equations in the random linear combination
+struct BatchEntry {
+    index: usize,
+    commitment: RistrettoPoint,
+    sig: Signature,
+    points: [RistrettoPoint; 4],
+    weights: [Scalar; 4],
+}
+
+impl PrecomputedVerifyingKey {
+    pub fn verifying_key(&self) -> &VerifyingKey {
+        &self.key
+    }
+
+    pub fn verify_prehashed(
+        &self,
+        hashed_message: &[u8],
+        commitment: &RistrettoPoint,
+        sig: &Signature,
+    ) -> Result<(), VerifyingError> {
+        self.batchable(hashed_message, commitment, sig).map(|_| ())
+    }
+
+    pub fn batchable(
+        &self,
+        hashed_message: &[u8],
+        commitment: &RistrettoPoint,
+        sig: &Signature,
+    ) -> Result<BatchableSignature, VerifyingError> {
+        let alpha = self.precomputation.vartime_multiscalar_mul([sig.rho, sig.omega]);
+
+        check_prehashed(alpha, hashed_message, commitment, sig)
+    }
+
+    // verify_batch checks a batch of (hashed_message, commitment, signature)
+    // entries and lists the indices of all the entries that don't verify. A
+    // plain signature's challenge hashes alpha, beta1, beta2 and eta, so they
+    // are recomputed for every entry, alpha from the shared precomputed table.
+    pub fn verify_batch(&self, batch: &[(&[u8], &RistrettoPoint, &Signature)]) -> Result<(), BatchVerifyingError> {
+        let failed: Vec<usize> = batch
+            .iter()
+            .enumerate()
+            .filter(|(_, (hashed_message, commitment, sig))| {
+                self.verify_prehashed(hashed_message, commitment, sig).is_err()
+            })
+            .map(|(index, _)| index)
+            .collect();
+
+        if failed.is_empty() {
             Ok(())
         } else {
-            Err(VerifyingError::Invalid)
+            Err(BatchVerifyingError { failed })
+        }
+    }
+
+    // verify_batchable is verify_batch for signatures that carry their points,
+    // see batchable. Every entry's challenge is checked against the points it
+    // carries, and then all the equations
+    //
+    //   alpha = rho * G + omega * Y
+    //   beta1 = sigma1 * G + delta * commitment
+    //   beta2 = sigma2 * H + delta * (xi - commitment)
+    //   eta   = mu * Z + delta * xi
+    //
+    // are checked at once, as a linear combination with weights hashed from the
+    // whole batch. If that fails, the batch is bisected until the entries that
+    // don't verify are found, and the error lists their indices.
+    pub fn verify_batchable(
+        &self,
+        batch: &[(&[u8], &RistrettoPoint, &BatchableSignature)],
+    ) -> Result<(), BatchVerifyingError> {
+        let mut hash = Sha512::new();
+        hash.update(b"acl batch verification");
+        hash.update(self.key.point.compress().as_bytes());
+        for (hashed_message, commitment, batchable) in batch {
+            hash.update((hashed_message.len() as u64).to_le_bytes());
+            hash.update(hashed_message);
+            hash.update(commitment.compress().as_bytes());
+            hash.update(batchable.to_bytes());
+        }
+        let seed = hash.finalize();
+
+        let mut failed = Vec::new();
+        let mut entries = Vec::new();
+        for (index, (hashed_message, commitment, batchable)) in batch.iter().enumerate() {
+            let sig = batchable.signature;
+            let compressed = [batchable.alpha, batchable.beta1, batchable.beta2, batchable.eta];
+            let check = compute_challenge_compressed(
+                &[
+                    sig.xi.compress(),
+                    commitment.compress(),
+                    compressed[0],
+                    compressed[1],
+                    compressed[2],
+                    compressed[3],
+                ],
+                hashed_message,
+            );
+            let points = compressed.iter().map(|point| point.decompress()).collect::<Option<Vec<_>>>();
+
+            match points {
+                Some(points) if check == sig.omega + sig.delta => entries.push(BatchEntry {
+                    index,
+                    commitment: **commitment,
+                    sig,
+                    points: [points[0], points[1], points[2], points[3]],
+                    weights: [0u8, 1, 2, 3].map(|equation| {
+                        let mut hash = Sha512::new();
+                        hash.update(seed);
+                        hash.update((index as u64).to_le_bytes());
+                        hash.update([equation]);
+                        Scalar::from_bytes_mod_order_wide(&hash.finalize().into())
+                    }),
+                }),
+                _ => failed.push(index),
+            }
+        }
+
+        self.bisect(&entries, &mut failed);
+
+        if failed.is_empty() {
+            Ok(())
+        } else {
+            failed.sort_unstable();
+            Err(BatchVerifyingError { failed })
+        }
+    }
+
+    // bisect adds the indices of the entries that don't verify to failed
+    fn bisect(&self, entries: &[BatchEntry], failed: &mut Vec<usize>) {
+        if entries.is_empty() || self.combine(entries) {
+            return;
+        }
+
+        if let [entry] = entries {
+            failed.push(entry.index);
+        } else {
+            let (left, right) = entries.split_at(entries.len() / 2);
+            self.bisect(left, failed);
+            self.bisect(right, failed);
+        }
+    }
+
+    // combine checks the entries' weighted verification equations in one
+    // multiscalar multiplication
+    fn combine(&self, entries: &[BatchEntry]) -> bool {
+        let mut g = Scalar::ZERO;
+        let mut y = Scalar::ZERO;
+        let mut h = Scalar::ZERO;
+        let mut z = Scalar::ZERO;
+        let mut scalars = Vec::with_capacity(6 * entries.len() + 2);
+        let mut points = Vec::with_capacity(6 * entries.len() + 2);
+
+        for entry in entries {
+            let sig = &entry.sig;
+            let [w0, w1, w2, w3] = entry.weights;
+
+            g += w0 * sig.rho + w1 * sig.sigma1;
+            y += w0 * sig.omega;
+            h += w2 * sig.sigma2;
+            z += w3 * sig.mu;
+
+            scalars.extend([-w0, -w1, -w2, -w3, (w1 - w2) * sig.delta, (w2 + w3) * sig.delta]);
+            points.extend(entry.points);
+            points.extend([entry.commitment, sig.xi]);
+        }
+        scalars.extend([h, z]);
+        points.extend([*gen_h(), *gen_z()]);
+
+        self.precomputation
+            .vartime_mixed_multiscalar_mul([g, y], scalars, points)
+            .is_identity()
+    }
+}
+
+// check_prehashed finishes verification once alpha, the only point that
+// depends on the issuer's public point, has been computed
+fn check_prehashed(
+    alpha: RistrettoPoint,
+    hashed_message: &[u8],
+    commitment: &RistrettoPoint,
+    sig: &Signature,
+) -> Result<BatchableSignature, VerifyingError> {
+    let batchable = BatchableSignature {
+        signature: *sig,
+        alpha: alpha.compress(),
+        beta1: RistrettoPoint::vartime_double_scalar_mul_basepoint(&sig.delta, commitment, &sig.sigma1).compress(),
+        beta2: RistrettoPoint::vartime_multiscalar_mul(
+            [sig.sigma2, sig.delta, -sig.delta],
+            [gen_h(), &sig.xi, commitment],
+        )
+        .compress(),
+        eta: RistrettoPoint::vartime_multiscalar_mul([sig.mu, sig.delta], [gen_z(), &sig.xi]).compress(),
+    };
+    let check = compute_challenge_compressed(
+        &[
+            sig.xi.compress(),
+            commitment.compress(),
+            batchable.alpha,
+            batchable.beta1,
+            batchable.beta2,
+            batchable.eta,
+        ],
+        hashed_message,
+    );
+
+    if check == sig.omega + sig.delta {
+        Ok(batchable)
+    } else {
+        Err(VerifyingError::Invalid)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::user::UserParameters;
+    use rand_core::OsRng;
+
+    fn issue(signing_key: &SigningKey, hashed_message: &[u8]) -> (RistrettoPoint, Signature) {
+        let commitment = RistrettoPoint::random(&mut OsRng);
+        let user_params = UserParameters {
+            key: VerifyingKey::from(signing_key),
+        };
+
+        let (ss, prepare_message) = signing_key.prepare(&commitment).unwrap();
+        let (us, challenge) = user_params
+            .compute_challenge(&mut OsRng, &commitment, hashed_message, &prepare_message)
+            .unwrap();
+        let presignature = signing_key.compute_presignature(ss, &challenge).unwrap();
+        let credential = user_params.compute_signature(&us, &presignature).unwrap();
+
+        (credential.blinded_commitment, credential.signature)
+    }
+
+    #[test]
+    fn verify_batch_pinpoints_failures() {
+        let signing_key = SigningKey::from_bytes(&[7u8; 32]);
+        let key = VerifyingKey::from(&signing_key);
+        let issued: Vec<(RistrettoPoint, BatchableSignature)> = (0..7)
+            .map(|_| {
+                let (commitment, sig) = issue(&signing_key, &[0u8; 64]);
+                (commitment, key.batchable(&[0u8; 64], &commitment, &sig).unwrap())
+            })
+            .collect();
+
+        let mut batch: Vec<(&[u8], &RistrettoPoint, &BatchableSignature)> = issued
+            .iter()
+            .map(|(commitment, sig)| (&[0u8; 64][..], commitment, sig))
+            .collect();
+        assert_eq!(key.verify_batchable(&batch), Ok(()));
+        assert_eq!(key.verify_batchable(&[]), Ok(()));
+
+        // a wrong rho leaves the challenge hash intact, so only the linear
+        // combination, and then bisection, can catch it
+        let mut forged = issued[5].1;
+        forged.signature.rho += Scalar::ONE;
+        batch[1].0 = &[1u8; 64];
+        batch[3].1 = &issued[4].0;
+        batch[5].2 = &forged;
+        assert_eq!(key.verify_batchable(&batch), Err(BatchVerifyingError { failed: vec![1, 3, 5] }));
+
+        let plain: Vec<(&[u8], &RistrettoPoint, &Signature)> = batch
+            .iter()
+            .map(|(hashed_message, commitment, batchable)| (*hashed_message, *commitment, &batchable.signature))
+            .collect();
+        assert_eq!(key.verify_batch(&plain), Err(BatchVerifyingError { failed: vec![1, 3, 5] }));
+        assert_eq!(key.verify_batch(&plain[..1]), Ok(()));
+        assert_eq!(key.verify_batch(&[]), Ok(()));
+
+        for (index, (hashed_message, commitment, sig)) in plain.iter().enumerate() {
+            assert_eq!(
+                key.verify_prehashed(hashed_message, commitment, sig).is_ok(),
+                ![1, 3, 5].contains(&index)
+            );
         }
     }
 }
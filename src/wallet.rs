@@ -0,0 +1,421 @@
+// Credential storage for users, behind the "wallet" feature.
+//
+// A wallet file is a short header followed by the entries as JSON, sealed with
+// AES-256-GCM under a key derived from the passphrase:
+//
+//   "acl wallet 1" || iterations (u32, little-endian) || salt (16) || nonce (12) || ciphertext
+//
+// The key is PBKDF2-HMAC-SHA512 of the passphrase with the salt, and the
+// header is authenticated along with the ciphertext. Exports use the same
+// format, under their own passphrase.
+use crate::errors::WalletError;
+use crate::token::Token;
+use crate::verifying::VerifyingKey;
+
+use aes_gcm::aead::{Aead, KeyInit, Payload};
+use aes_gcm::{Aes256Gcm, Nonce};
+
+use curve25519_dalek::scalar::Scalar;
+
+use hmac::Hmac;
+
+use rand_core::{CryptoRng, RngCore};
+
+use serde::{Deserialize, Serialize};
+
+use sha2::Sha512;
+
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+const MAGIC: &[u8] = b"acl wallet 1";
+const HEADER_LENGTH: usize = MAGIC.len() + 4 + 16 + 12;
+
+// OWASP's recommendation for PBKDF2-HMAC-SHA512
+pub const DEFAULT_ITERATIONS: u32 = 210_000;
+
+// Iteration counts are read from files that may come from someone else, so
+// they are bounded both ways: too few would make the passphrase cheap to guess,
+// too many would make opening the file hang
+pub const MIN_ITERATIONS: u32 = 100_000;
+pub const MAX_ITERATIONS: u32 = 10_000_000;
+
+// WalletEntry is one credential: the token (signature, blinded commitment,
+// blinding secrets and the opening of its attributes), who issued it under
+// which key, the schema's attribute names, and free-form metadata such as an
+// expiry date or a label
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct WalletEntry {
+    pub issuer: String,
+    pub key_id: [u8; 32],
+    pub attributes: Vec<String>,
+    pub token: Token,
+    pub metadata: BTreeMap<String, String>,
+}
+
+// Wallet holds a user's credentials in memory and in an encrypted file. The
+// file is only written by save.
+pub struct Wallet {
+    path: PathBuf,
+    key: [u8; 32],
+    iterations: u32,
+    salt: [u8; 16],
+    entries: Vec<WalletEntry>,
+}
+
+impl WalletEntry {
+    pub fn new<I: AsRef<str>>(issuer: &str, key: &VerifyingKey, attributes: &[I], token: Token) -> WalletEntry {
+        WalletEntry {
+            issuer: issuer.to_string(),
+            key_id: key.key_id(),
+            attributes: attributes.iter().map(|name| name.as_ref().to_string()).collect(),
+            token,
+            metadata: BTreeMap::new(),
+        }
+    }
+
+    // attribute returns the value of the attribute with the given name
+    pub fn attribute(&self, name: &str) -> Option<&Scalar> {
+        let index = self.attributes.iter().position(|attribute| attribute == name)?;
+        self.token.opening.attributes.get(index)
+    }
+}
+
+impl Wallet {
+    // create starts an empty wallet that will be saved at path, and fails if
+    // a file already exists there. The path is claimed with create_new, so two
+    // wallets created at once can't both succeed and overwrite each other.
+    pub fn create<P: AsRef<Path>, R: RngCore + CryptoRng>(
+        rng: &mut R,
+        path: P,
+        passphrase: &str,
+        iterations: u32,
+    ) -> Result<Wallet, WalletError> {
+        check_iterations(iterations)?;
+        OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(path.as_ref())
+            .map_err(|err| match err.kind() {
+                ErrorKind::AlreadyExists => WalletError::Exists,
+                _ => WalletError::from(err),
+            })?;
+
+        let mut salt = [0u8; 16];
+        rng.fill_bytes(&mut salt);
+
+        let wallet = Wallet {
+            path: path.as_ref().to_path_buf(),
+            key: derive_key(passphrase, &salt, iterations),
+            iterations,
+            salt,
+            entries: Vec::new(),
+        };
+        if let Err(err) = wallet.save(rng) {
+            let _ = fs::remove_file(path.as_ref());
+            return Err(err);
+        }
+        Ok(wallet)
+    }
+
+    pub fn open<P: AsRef<Path>>(path: P, passphrase: &str) -> Result<Wallet, WalletError> {
+        let sealed = fs::read(path.as_ref())?;
+        let (iterations, salt) = header(&sealed)?;
+        let key = derive_key(passphrase, &salt, iterations);
+
+        Ok(Wallet {
+            path: path.as_ref().to_path_buf(),
+            entries: unseal(&key, &sealed)?,
+            key,
+            iterations,
+            salt,
+        })
+    }
+
+    // save writes the wallet to a temporary file next to it and renames that
+    // over the wallet, so that a crash leaves either the old or the new file.
+    // The temporary file is synced before the rename, so the rename can't land
+    // ahead of its contents, and the directory after it, so the rename itself
+    // survives a power loss.
+    pub fn save<R: RngCore + CryptoRng>(&self, rng: &mut R) -> Result<(), WalletError> {
+        let sealed = seal(rng, &self.key, self.iterations, &self.salt, &self.entries);
+
+        let mut temporary = self.path.clone().into_os_string();
+        temporary.push(".tmp");
+        let mut file = File::create(&temporary)?;
+        file.write_all(&sealed)?;
+        file.sync_all()?;
+        drop(file);
+        fs::rename(&temporary, &self.path)?;
+
+        // only unix lets a directory be opened and synced like a file
+        #[cfg(unix)]
+        {
+            let directory = match self.path.parent() {
+                Some(parent) if !parent.as_os_str().is_empty() => parent,
+                _ => Path::new("."),
+            };
+            File::open(directory)?.sync_all()?;
+        }
+        Ok(())
+    }
+
+    pub fn entries(&self) -> &[WalletEntry] {
+        &self.entries
+    }
+
+    pub fn insert(&mut self, entry: WalletEntry) {
+        self.entries.push(entry);
+    }
+
+    // take removes the entry at position, e.g. to spend its token, which can
+    // only be shown once
+    pub fn take(&mut self, position: usize) -> Option<WalletEntry> {
+        (position < self.entries.len()).then(|| self.entries.remove(position))
+    }
+
+    pub fn by_issuer<'a>(&'a self, issuer: &'a str) -> impl Iterator<Item = (usize, &'a WalletEntry)> + 'a {
+        self.entries
+            .iter()
+            .enumerate()
+            .filter(move |(_, entry)| entry.issuer == issuer)
+    }
+
+    pub fn by_attribute<'a>(
+        &'a self,
+        name: &'a str,
+        value: &'a Scalar,
+    ) -> impl Iterator<Item = (usize, &'a WalletEntry)> + 'a {
+        self.entries
+            .iter()
+            .enumerate()
+            .filter(move |(_, entry)| entry.attribute(name) == Some(value))
+    }
+
+    // export seals the entries at positions under their own passphrase, for
+    // import into another wallet
+    pub fn export<R: RngCore + CryptoRng>(
+        &self,
+        rng: &mut R,
+        positions: &[usize],
+        passphrase: &str,
+        iterations: u32,
+    ) -> Result<Vec<u8>, WalletError> {
+        check_iterations(iterations)?;
+        let entries = positions
+            .iter()
+            .map(|position| self.entries.get(*position).cloned().ok_or(WalletError::Position))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut salt = [0u8; 16];
+        rng.fill_bytes(&mut salt);
+        let key = derive_key(passphrase, &salt, iterations);
+
+        Ok(seal(rng, &key, iterations, &salt, &entries))
+    }
+
+    // import adds the entries of an export and returns how many there were.
+    // Like insert, it doesn't save the wallet.
+    pub fn import(&mut self, exported: &[u8], passphrase: &str) -> Result<usize, WalletError> {
+        let (iterations, salt) = header(exported)?;
+        let entries = unseal(&derive_key(passphrase, &salt, iterations), exported)?;
+
+        let count = entries.len();
+        self.entries.extend(entries);
+        Ok(count)
+    }
+}
+
+fn seal<R: RngCore + CryptoRng>(
+    rng: &mut R,
+    key: &[u8; 32],
+    iterations: u32,
+    salt: &[u8; 16],
+    entries: &[WalletEntry],
+) -> Vec<u8> {
+    let mut nonce = [0u8; 12];
+    rng.fill_bytes(&mut nonce);
+
+    let mut sealed = [MAGIC, &iterations.to_le_bytes(), salt, &nonce].concat();
+    let plaintext = serde_json::to_vec(entries).expect("wallet entries always serialize");
+    let ciphertext = Aes256Gcm::new(key.into())
+        .encrypt(
+            Nonce::from_slice(&nonce),
+            Payload {
+                msg: &plaintext,
+                aad: &sealed,
+            },
+        )
+        .expect("plaintext is within AES-GCM's length limit");
+
+    sealed.extend_from_slice(&ciphertext);
+    sealed
+}
+
+fn header(sealed: &[u8]) -> Result<(u32, [u8; 16]), WalletError> {
+    if sealed.len() < HEADER_LENGTH || !sealed.starts_with(MAGIC) {
+        return Err(WalletError::Format);
+    }
+
+    let iterations = u32::from_le_bytes(sealed[MAGIC.len()..MAGIC.len() + 4].try_into()?);
+    let salt = sealed[MAGIC.len() + 4..MAGIC.len() + 20].try_into()?;
+    check_iterations(iterations)?;
+    Ok((iterations, salt))
+}
+
+fn check_iterations(iterations: u32) -> Result<(), WalletError> {
+    if (MIN_ITERATIONS..=MAX_ITERATIONS).contains(&iterations) {
+        Ok(())
+    } else {
+        Err(WalletError::Iterations)
+    }
+}
+
+fn unseal(key: &[u8; 32], sealed: &[u8]) -> Result<Vec<WalletEntry>, WalletError> {
+    let (header, ciphertext) = sealed.split_at(HEADER_LENGTH);
+    let plaintext = Aes256Gcm::new(key.into())
+        .decrypt(
+            Nonce::from_slice(&header[HEADER_LENGTH - 12..]),
+            Payload {
+                msg: ciphertext,
+                aad: header,
+            },
+        )
+        .map_err(|_| WalletError::Passphrase)?;
+
+    serde_json::from_slice(&plaintext).map_err(|_| WalletError::Format)
+}
+
+// derive_key is PBKDF2-HMAC-SHA512 (RFC 8018)
+fn derive_key(passphrase: &str, salt: &[u8], iterations: u32) -> [u8; 32] {
+    let mut key = [0u8; 32];
+    pbkdf2::pbkdf2::<Hmac<Sha512>>(passphrase.as_bytes(), salt, iterations, &mut key)
+        .expect("HMAC takes keys of any length");
+    key
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::attributes::Schema;
+    use crate::signing::SigningKey;
+    use crate::token::tests::issue;
+    use rand_core::OsRng;
+
+    const ATTRIBUTES: [&str; 4] = ["user id", "type", "sports", "tech"];
+
+    fn entry(issuer: &str, signing_key: &SigningKey, kind: u8) -> WalletEntry {
+        let token = issue(
+            signing_key,
+            &Schema::new(&ATTRIBUTES),
+            vec![Scalar::from(1234u16), Scalar::from(kind), Scalar::ONE, Scalar::ZERO],
+        );
+        WalletEntry::new(issuer, &VerifyingKey::from(signing_key), &ATTRIBUTES, token)
+    }
+
+    fn path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("acl-wallet-{}-{}", name, std::process::id()));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn pbkdf2_matches_a_known_answer() {
+        assert_eq!(
+            derive_key("password", b"salt", 2),
+            [
+                0xe1, 0xd9, 0xc1, 0x6a, 0xa6, 0x81, 0x70, 0x8a, 0x45, 0xf5, 0xc7, 0xc4, 0xe2, 0x15, 0xce, 0xb6, 0x6e,
+                0x01, 0x1a, 0x2e, 0x9f, 0x00, 0x40, 0x71, 0x3f, 0x18, 0xae, 0xfd, 0xb8, 0x66, 0xd5, 0x3c,
+            ]
+        );
+    }
+
+    #[test]
+    fn wallets_persist_encrypted() {
+        let news = SigningKey::from_bytes(&[7u8; 32]);
+        let sports = SigningKey::from_bytes(&[8u8; 32]);
+        let path = path("persist");
+
+        assert_eq!(
+            Wallet::create(&mut OsRng, &path, "correct horse", 1000).err(),
+            Some(WalletError::Iterations)
+        );
+        assert!(!path.exists());
+
+        let mut wallet = Wallet::create(&mut OsRng, &path, "correct horse", MIN_ITERATIONS).unwrap();
+        let mut first = entry("news", &news, 2);
+        first.metadata.insert("expiry".to_string(), "2030-01-01".to_string());
+        wallet.insert(first.clone());
+        wallet.insert(entry("sports", &sports, 1));
+        wallet.insert(entry("news", &news, 1));
+        wallet.save(&mut OsRng).unwrap();
+
+        let sealed = fs::read(&path).unwrap();
+        assert!(!sealed.windows(7).any(|window| window == b"user id"));
+        assert_eq!(
+            Wallet::open(&path, "battery staple").err(),
+            Some(WalletError::Passphrase)
+        );
+        assert_eq!(
+            Wallet::create(&mut OsRng, &path, "correct horse", MIN_ITERATIONS).err(),
+            Some(WalletError::Exists)
+        );
+
+        let mut wallet = Wallet::open(&path, "correct horse").unwrap();
+        assert_eq!(wallet.entries()[0], first);
+        assert_eq!(
+            wallet.by_issuer("news").map(|(position, _)| position).collect::<Vec<_>>(),
+            vec![0, 2]
+        );
+        assert_eq!(
+            wallet
+                .by_attribute("type", &Scalar::from(1u8))
+                .map(|(position, _)| position)
+                .collect::<Vec<_>>(),
+            vec![1, 2]
+        );
+        assert_eq!(wallet.entries()[1].key_id, VerifyingKey::from(&sports).key_id());
+
+        assert_eq!(wallet.take(1).unwrap().issuer, "sports");
+        wallet.save(&mut OsRng).unwrap();
+        assert_eq!(Wallet::open(&path, "correct horse").unwrap().entries().len(), 2);
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn exports_import_into_another_wallet() {
+        let news = SigningKey::from_bytes(&[7u8; 32]);
+        let (from, to) = (path("export"), path("import"));
+
+        let mut source = Wallet::create(&mut OsRng, &from, "correct horse", MIN_ITERATIONS).unwrap();
+        source.insert(entry("news", &news, 1));
+        source.insert(entry("news", &news, 2));
+
+        let exported = source.export(&mut OsRng, &[1], "transfer", MIN_ITERATIONS).unwrap();
+        assert_eq!(
+            source.export(&mut OsRng, &[2], "transfer", MIN_ITERATIONS).err(),
+            Some(WalletError::Position)
+        );
+        assert_eq!(
+            source.export(&mut OsRng, &[1], "transfer", MAX_ITERATIONS + 1).err(),
+            Some(WalletError::Iterations)
+        );
+
+        let mut target = Wallet::create(&mut OsRng, &to, "battery staple", MIN_ITERATIONS).unwrap();
+        assert_eq!(target.import(&exported, "wrong").err(), Some(WalletError::Passphrase));
+
+        // the count is checked before any key is derived from it
+        let mut expensive = exported.clone();
+        expensive[MAGIC.len()..MAGIC.len() + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert_eq!(target.import(&expensive, "transfer").err(), Some(WalletError::Iterations));
+        assert_eq!(target.import(&exported[1..], "transfer").err(), Some(WalletError::Format));
+        assert_eq!(target.import(&exported, "transfer"), Ok(1));
+        assert_eq!(target.entries(), &source.entries()[1..]);
+
+        fs::remove_file(&from).unwrap();
+        fs::remove_file(&to).unwrap();
+    }
+}
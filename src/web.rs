@@ -0,0 +1,240 @@
+// Rocket integration, behind the "rocket" feature.
+//
+// Presentations travel in the X-ACL-Presentation header as base64 of their
+// JSON encoding, see encode_presentation. A route takes a Presented<S>
+// guard, where S: CredentialSchema names the schema's attributes and the
+// policy to check, and the application manages a CredentialConfig<S> with the
+// issuer keys to accept and a store for replay protection:
+//
+//   rocket::ignite()
+//       .manage(CredentialConfig::<Subscriber>::new(&[key], store, b"news.example"))
+//       .mount("/", routes![articles])
+//
+// A request without a valid, fresh presentation never reaches the handler.
+use crate::attributes::Schema;
+use crate::errors::{CredentialError, VerifyingError};
+use crate::presentation::{Presentation, PresentationPolicy};
+use crate::spent::{SpentTokenStore, Verifier};
+use crate::verifying::VerifyingKey;
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+
+use curve25519_dalek::ristretto::RistrettoPoint;
+use curve25519_dalek::scalar::Scalar;
+
+use rocket::http::Status;
+use rocket::request::{self, FromRequest, Request};
+use rocket::{Outcome, State};
+
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+pub const PRESENTATION_HEADER: &str = "X-ACL-Presentation";
+
+// CredentialSchema describes one kind of credential a service accepts
+pub trait CredentialSchema: 'static {
+    // ATTRIBUTES names the schema's attributes, in schema order
+    const ATTRIBUTES: &'static [&'static str];
+
+    // policy is the presentation policy for a request, e.g. with a rate
+    // limit epoch derived from the current date
+    fn policy(request: &Request) -> PresentationPolicy;
+}
+
+// CredentialConfig is the managed state behind Presented<S>: the accepted
+// issuer keys, one Verifier each, all sharing one spent-token store, and the
+// context presentations must be bound to (e.g. the service's name).
+pub struct CredentialConfig<S: CredentialSchema> {
+    schema: Schema,
+    verifiers: Vec<Verifier<Arc<dyn SpentTokenStore + Send + Sync>>>,
+    context: Vec<u8>,
+    schema_type: PhantomData<fn() -> S>,
+}
+
+// Presented is a request guard for a verified presentation of an S
+// credential, exposing what it reveals to the handler
+pub struct Presented<S: CredentialSchema> {
+    pub key: VerifyingKey,
+    pub revealed: Vec<(usize, Scalar)>,
+    pub pseudonym: Option<RistrettoPoint>,
+    schema_type: PhantomData<fn() -> S>,
+}
+
+impl<S: CredentialSchema> CredentialConfig<S> {
+    pub fn new(keys: &[VerifyingKey], store: Arc<dyn SpentTokenStore + Send + Sync>, context: &[u8]) -> Self {
+        CredentialConfig {
+            schema: Schema::new(S::ATTRIBUTES),
+            verifiers: keys.iter().map(|key| Verifier::new(key, store.clone())).collect(),
+            context: context.to_vec(),
+            schema_type: PhantomData,
+        }
+    }
+
+    pub fn schema(&self) -> &Schema {
+        &self.schema
+    }
+
+    pub fn context(&self) -> &[u8] {
+        &self.context
+    }
+
+    // verify checks a presentation against the key that signed it, and records
+    // it as spent. Verification starts with the issuer's signature, and only
+    // an invalid signature fails with VerifyingError::Invalid, so each key's
+    // signature check runs once at most.
+    pub fn verify(
+        &self,
+        presentation: &Presentation,
+        policy: &PresentationPolicy,
+    ) -> Result<VerifyingKey, VerifyingError> {
+        for verifier in self.verifiers.iter() {
+            match verifier.verify_presentation(presentation, &self.schema, policy, &self.context) {
+                Err(VerifyingError::Invalid) => continue,
+                result => return result.map(|_| *verifier.verifying_key()),
+            }
+        }
+
+        Err(VerifyingError::Invalid)
+    }
+}
+
+impl<S: CredentialSchema> Presented<S> {
+    // attribute returns a revealed attribute by name
+    pub fn attribute(&self, name: &str) -> Option<&Scalar> {
+        let index = S::ATTRIBUTES.iter().position(|attribute| *attribute == name)?;
+        self.revealed
+            .iter()
+            .find(|(revealed, _)| *revealed == index)
+            .map(|(_, value)| value)
+    }
+
+    fn from_header(request: &Request) -> Result<Self, (Status, CredentialError)> {
+        let config = request
+            .guard::<State<CredentialConfig<S>>>()
+            .succeeded()
+            .ok_or((Status::InternalServerError, CredentialError::Unconfigured))?;
+
+        let header = request
+            .headers()
+            .get_one(PRESENTATION_HEADER)
+            .ok_or((Status::Unauthorized, CredentialError::Missing))?;
+        let presentation = decode_presentation(header).map_err(|err| (Status::BadRequest, err))?;
+
+        let policy = S::policy(request);
+        let key = config.verify(&presentation, &policy).map_err(|err| {
+            let status = match err {
+                VerifyingError::RateLimited => Status::TooManyRequests,
+                VerifyingError::Store { .. } => Status::InternalServerError,
+                _ => Status::Unauthorized,
+            };
+            (status, CredentialError::Invalid { err })
+        })?;
+
+        Ok(Presented {
+            key,
+            revealed: presentation.revealed,
+            pseudonym: presentation.pseudonym,
+            schema_type: PhantomData,
+        })
+    }
+}
+
+impl<'a, 'r, S: CredentialSchema> FromRequest<'a, 'r> for Presented<S> {
+    type Error = CredentialError;
+
+    fn from_request(request: &'a Request<'r>) -> request::Outcome<Self, CredentialError> {
+        match Presented::<S>::from_header(request) {
+            Ok(presented) => Outcome::Success(presented),
+            Err(failure) => Outcome::Failure(failure),
+        }
+    }
+}
+
+pub fn encode_presentation(presentation: &Presentation) -> String {
+    STANDARD.encode(serde_json::to_vec(presentation).expect("presentations always serialize"))
+}
+
+pub fn decode_presentation(header: &str) -> Result<Presentation, CredentialError> {
+    let json = STANDARD.decode(header).map_err(|_| CredentialError::Format)?;
+    serde_json::from_slice(&json).map_err(|_| CredentialError::Format)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::signing::SigningKey;
+    use crate::spent::MemorySpentTokenStore;
+    use crate::token::tests::issue;
+    use rand_core::OsRng;
+    use rocket::http::Header;
+    use rocket::local::Client;
+
+    struct Subscriber;
+
+    impl CredentialSchema for Subscriber {
+        const ATTRIBUTES: &'static [&'static str] = &["user id", "type", "sports", "tech"];
+
+        fn policy(_: &Request) -> PresentationPolicy {
+            PresentationPolicy {
+                revealed: vec![1],
+                ..Default::default()
+            }
+        }
+    }
+
+    #[get("/articles")]
+    fn articles(subscriber: Presented<Subscriber>) -> String {
+        format!("{:?}", subscriber.attribute("type").map(Scalar::to_bytes).map(|bytes| bytes[0]))
+    }
+
+    #[test]
+    fn guard_accepts_each_presentation_once() {
+        let signing_key = SigningKey::from_bytes(&[7u8; 32]);
+        let key = VerifyingKey::from(&signing_key);
+        let other_key = VerifyingKey::from(&SigningKey::from_bytes(&[8u8; 32]));
+        let schema = Schema::new(Subscriber::ATTRIBUTES);
+
+        let config = CredentialConfig::<Subscriber>::new(
+            &[other_key, key],
+            Arc::new(MemorySpentTokenStore::new()),
+            b"news.example",
+        );
+        let client = Client::new(rocket::ignite().manage(config).mount("/", routes![articles])).unwrap();
+
+        let token = issue(&signing_key, &schema, vec![Scalar::from(5u8), Scalar::from(2u8), Scalar::ONE, Scalar::ZERO]);
+        let policy = PresentationPolicy {
+            revealed: vec![1],
+            ..Default::default()
+        };
+        let presentation = Presentation::new(&mut OsRng, &token, &key, &schema, &policy, b"news.example").unwrap();
+        let header = || Header::new(PRESENTATION_HEADER, encode_presentation(&presentation));
+
+        let mut response = client.get("/articles").header(header()).dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.body_string(), Some("Some(2)".to_string()));
+
+        assert_eq!(client.get("/articles").header(header()).dispatch().status(), Status::Unauthorized);
+        assert_eq!(client.get("/articles").dispatch().status(), Status::Unauthorized);
+        assert_eq!(
+            client
+                .get("/articles")
+                .header(Header::new(PRESENTATION_HEADER, "not base64"))
+                .dispatch()
+                .status(),
+            Status::BadRequest
+        );
+
+        // a presentation for another service is rejected
+        let token = issue(&signing_key, &schema, vec![Scalar::from(5u8), Scalar::from(2u8), Scalar::ONE, Scalar::ZERO]);
+        let elsewhere = Presentation::new(&mut OsRng, &token, &key, &schema, &policy, b"other.example").unwrap();
+        assert_eq!(
+            client
+                .get("/articles")
+                .header(Header::new(PRESENTATION_HEADER, encode_presentation(&elsewhere)))
+                .dispatch()
+                .status(),
+            Status::Unauthorized
+        );
+    }
+}